@@ -1,12 +1,24 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20250611_074134_create_initial_tables;
+mod m20250615_120000_add_location_details_to_history;
+mod m20250618_093000_create_friend_sessions;
+mod m20250620_101500_create_pipeline_events;
+mod m20250622_140000_create_event_dead_letters;
+mod m20250626_090000_add_observer_user_id;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20250611_074134_create_initial_tables::Migration)]
+        vec![
+            Box::new(m20250611_074134_create_initial_tables::Migration),
+            Box::new(m20250615_120000_add_location_details_to_history::Migration),
+            Box::new(m20250618_093000_create_friend_sessions::Migration),
+            Box::new(m20250620_101500_create_pipeline_events::Migration),
+            Box::new(m20250622_140000_create_event_dead_letters::Migration),
+            Box::new(m20250626_090000_add_observer_user_id::Migration),
+        ]
     }
 }
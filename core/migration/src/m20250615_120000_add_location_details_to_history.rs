@@ -0,0 +1,82 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only accepts a single change per ALTER TABLE statement.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserLocationHistory::Table)
+                    .add_column(ColumnDef::new(UserLocationHistory::InstanceName).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserLocationHistory::Table)
+                    .add_column(ColumnDef::new(UserLocationHistory::AccessType).string())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserLocationHistory::Table)
+                    .add_column(ColumnDef::new(UserLocationHistory::Region).string())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-location-history-world_id")
+                    .table(UserLocationHistory::Table)
+                    .col(UserLocationHistory::WorldId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx-location-history-world_id")
+                    .table(UserLocationHistory::Table)
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            UserLocationHistory::Region,
+            UserLocationHistory::AccessType,
+            UserLocationHistory::InstanceName,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserLocationHistory::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserLocationHistory {
+    Table,
+    WorldId,
+    InstanceName,
+    AccessType,
+    Region,
+}
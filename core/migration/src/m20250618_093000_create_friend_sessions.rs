@@ -0,0 +1,85 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(FriendSessions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(FriendSessions::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(FriendSessions::UserId).string().not_null())
+                    .col(ColumnDef::new(FriendSessions::Platform).string())
+                    .col(ColumnDef::new(FriendSessions::State).string().not_null())
+                    .col(
+                        ColumnDef::new(FriendSessions::StartedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(
+                        ColumnDef::new(FriendSessions::LastSeenAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .col(ColumnDef::new(FriendSessions::EndedAt).timestamp_with_time_zone())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk-friend-sessions-user_id")
+                            .from(FriendSessions::Table, FriendSessions::UserId)
+                            .to(Users::Table, Users::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-friend-sessions-user_id-ended_at")
+                    .table(FriendSessions::Table)
+                    .col(FriendSessions::UserId)
+                    .col(FriendSessions::EndedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(FriendSessions::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Users {
+    Table,
+    Id,
+}
+#[derive(DeriveIden)]
+enum FriendSessions {
+    Table,
+    Id,
+    UserId,
+    Platform,
+    State,
+    StartedAt,
+    LastSeenAt,
+    EndedAt,
+}
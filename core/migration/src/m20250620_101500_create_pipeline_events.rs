@@ -0,0 +1,80 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(PipelineEvents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(PipelineEvents::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(PipelineEvents::EventType)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(PipelineEvents::Content).json())
+                    .col(ColumnDef::new(PipelineEvents::Outcome).string().not_null())
+                    .col(ColumnDef::new(PipelineEvents::Error).text())
+                    .col(
+                        ColumnDef::new(PipelineEvents::ReceivedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-pipeline-events-received_at")
+                    .table(PipelineEvents::Table)
+                    .col(PipelineEvents::ReceivedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-pipeline-events-event_type-received_at")
+                    .table(PipelineEvents::Table)
+                    .col(PipelineEvents::EventType)
+                    .col(PipelineEvents::ReceivedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(PipelineEvents::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum PipelineEvents {
+    Table,
+    Id,
+    EventType,
+    Content,
+    Outcome,
+    Error,
+    ReceivedAt,
+}
@@ -0,0 +1,77 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(EventDeadLetters::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(EventDeadLetters::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(EventDeadLetters::EventType)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EventDeadLetters::ErrorPath)
+                            .string()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(EventDeadLetters::ErrorMessage)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(EventDeadLetters::Payload).json().not_null())
+                    .col(
+                        ColumnDef::new(EventDeadLetters::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-event-dead-letters-event_type")
+                    .table(EventDeadLetters::Table)
+                    .col(EventDeadLetters::EventType)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(EventDeadLetters::Table).to_owned())
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum EventDeadLetters {
+    Table,
+    Id,
+    EventType,
+    ErrorPath,
+    ErrorMessage,
+    Payload,
+    CreatedAt,
+}
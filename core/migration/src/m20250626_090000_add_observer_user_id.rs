@@ -0,0 +1,102 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+/// Tables whose rows are observations made through one VRChat account, with the name of
+/// the index on their new column.
+fn observed_tables() -> [(DynIden, &'static str); 4] {
+    [
+        (
+            PipelineEvents::Table.into_iden(),
+            "idx-pipeline-events-observer_user_id",
+        ),
+        (
+            EventDeadLetters::Table.into_iden(),
+            "idx-event-dead-letters-observer_user_id",
+        ),
+        (
+            FriendSessions::Table.into_iden(),
+            "idx-friend-sessions-observer_user_id",
+        ),
+        (
+            UserLocationHistory::Table.into_iden(),
+            "idx-location-history-observer_user_id",
+        ),
+    ]
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Nullable, since rows written before multi-account support have no known observer.
+        for (table, index_name) in observed_tables() {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table.clone())
+                        .add_column(ColumnDef::new(Observed::ObserverUserId).string())
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .create_index(
+                    Index::create()
+                        .name(index_name)
+                        .table(table)
+                        .col(Observed::ObserverUserId)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for (table, index_name) in observed_tables() {
+            manager
+                .drop_index(
+                    Index::drop()
+                        .name(index_name)
+                        .table(table.clone())
+                        .to_owned(),
+                )
+                .await?;
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(table)
+                        .drop_column(Observed::ObserverUserId)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum PipelineEvents {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum EventDeadLetters {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum FriendSessions {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum UserLocationHistory {
+    Table,
+}
+
+#[derive(DeriveIden)]
+enum Observed {
+    ObserverUserId,
+}
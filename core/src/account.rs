@@ -0,0 +1,207 @@
+use crate::client::{VrcApiClient, GLOBAL_API_CLIENT};
+use crate::friends::FriendSync;
+use crate::models::LoginCredentials;
+use crate::pipeline::{PipelineManager, PipelineStatus};
+use crate::session_store::PersistedSession;
+use crate::two_factor::TwoFactorProvider;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use vrchatapi::apis::configuration::Configuration;
+
+/// Every logged-in account, keyed by VRChat user id.
+pub static GLOBAL_ACCOUNTS: LazyLock<AccountRegistry> = LazyLock::new(AccountRegistry::new);
+
+/// One VRChat account: its API client and cookie jar, and once logged in, its friend
+/// sync and pipeline connection.
+///
+/// Log in with `auth::login`, which registers the account in `GLOBAL_ACCOUNTS`.
+pub struct AccountSession {
+    client: RwLock<VrcApiClient>,
+    session: Option<PersistedSession>,
+    two_factor: RwLock<Arc<dyn TwoFactorProvider>>,
+    user_id: RwLock<Option<String>>,
+    friend_sync: Mutex<Option<FriendSync>>,
+    pipeline: tokio::sync::Mutex<Option<PipelineManager>>,
+}
+
+impl AccountSession {
+    /// Creates an account on a copy of `client`, keeping its cookies in `session` if given.
+    pub fn new(
+        mut client: VrcApiClient,
+        session: Option<PersistedSession>,
+        two_factor: Arc<dyn TwoFactorProvider>,
+    ) -> Result<Self, reqwest::Error> {
+        if let Some(session) = &session {
+            client.config.client = reqwest::Client::builder()
+                .cookie_provider(session.cookies())
+                .build()?;
+        }
+
+        Ok(Self {
+            client: RwLock::new(client),
+            session,
+            two_factor: RwLock::new(two_factor),
+            user_id: RwLock::new(None),
+            friend_sync: Mutex::new(None),
+            pipeline: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    /// Creates an account from the `GLOBAL_API_CLIENT` defaults, with its cookies in
+    /// `session`, usually `PersistedSession::for_account` so that no two accounts share
+    /// a cookie file.
+    pub fn with_defaults(
+        session: PersistedSession,
+        two_factor: Arc<dyn TwoFactorProvider>,
+    ) -> Result<Self, reqwest::Error> {
+        let client = VrcApiClient {
+            config: GLOBAL_API_CLIENT.read().unwrap().config.clone(),
+        };
+        Self::new(client, Some(session), two_factor)
+    }
+
+    pub fn set_credentials(&self, credentials: &LoginCredentials) {
+        self.client.write().unwrap().config.basic_auth =
+            Some((credentials.username.clone(), credentials.password.clone()));
+        log::info!("Updated basic auth for user: {}", credentials.username);
+    }
+
+    pub fn set_two_factor(&self, two_factor: Arc<dyn TwoFactorProvider>) {
+        *self.two_factor.write().unwrap() = two_factor;
+    }
+
+    pub fn two_factor(&self) -> Arc<dyn TwoFactorProvider> {
+        self.two_factor.read().unwrap().clone()
+    }
+
+    /// The API configuration of this account, for calls into `vrchatapi`.
+    pub fn config(&self) -> Configuration {
+        self.client.read().unwrap().config.clone()
+    }
+
+    /// The VRChat user id, once logged in.
+    pub fn user_id(&self) -> Option<String> {
+        self.user_id.read().unwrap().clone()
+    }
+
+    pub(crate) fn set_user_id(&self, user_id: String) {
+        *self.user_id.write().unwrap() = Some(user_id);
+    }
+
+    /// Saves the session cookies, if this account persists them.
+    pub fn save_session(&self) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        match session.save() {
+            Ok(()) => log::info!("Cookies saved successfully."),
+            Err(e) => log::error!("Failed to save cookies: {}", e),
+        }
+    }
+
+    /// Starts syncing the friend list of `owner_user_id`, replacing any previous sync.
+    pub(crate) fn start_friend_sync(&self, owner_user_id: String) {
+        let friend_sync = FriendSync::start(self.config(), owner_user_id);
+        // Dropping the previous sync stops it.
+        *self.friend_sync.lock().unwrap() = Some(friend_sync);
+    }
+
+    /// Asks the friend sync to run as soon as possible, e.g. after a pipeline reconnect.
+    pub fn request_friend_sync(&self) {
+        if let Some(friend_sync) = self.friend_sync.lock().unwrap().as_ref() {
+            friend_sync.request();
+        }
+    }
+
+    /// Starts `manager`, shutting down the pipeline it replaces.
+    pub(crate) async fn start_pipeline(&self, mut manager: PipelineManager) {
+        let mut pipeline = self.pipeline.lock().await;
+        if let Some(mut previous) = pipeline.take() {
+            previous.shutdown().await;
+        }
+        manager.start().await;
+        *pipeline = Some(manager);
+    }
+
+    pub async fn pipeline_status(&self) -> Option<PipelineStatus> {
+        let pipeline = self.pipeline.lock().await;
+        match pipeline.as_ref() {
+            Some(manager) => Some(manager.get_status().await),
+            None => None,
+        }
+    }
+
+    /// Stops the pipeline, waiting for queued events and pending offlines to be persisted,
+    /// and the friend sync.
+    pub async fn shutdown(&self) {
+        if let Some(mut manager) = self.pipeline.lock().await.take() {
+            manager.shutdown().await;
+        }
+        self.friend_sync.lock().unwrap().take();
+    }
+}
+
+/// Logged-in accounts by user id, so one process can watch several of them.
+pub struct AccountRegistry {
+    accounts: tokio::sync::RwLock<HashMap<String, Arc<AccountSession>>>,
+}
+
+impl Default for AccountRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AccountRegistry {
+    pub fn new() -> Self {
+        Self {
+            accounts: tokio::sync::RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Registers a logged-in `account`, shutting down a different session previously
+    /// registered for the same user. Accounts without a user id are ignored.
+    pub async fn insert(&self, account: Arc<AccountSession>) {
+        let Some(user_id) = account.user_id() else {
+            log::warn!("Not registering an account that is not logged in");
+            return;
+        };
+
+        let previous = self
+            .accounts
+            .write()
+            .await
+            .insert(user_id.clone(), account.clone());
+        if let Some(previous) = previous.filter(|previous| !Arc::ptr_eq(previous, &account)) {
+            log::info!("Replacing the previous session of {}", user_id);
+            previous.shutdown().await;
+        }
+    }
+
+    pub async fn get(&self, user_id: &str) -> Option<Arc<AccountSession>> {
+        self.accounts.read().await.get(user_id).cloned()
+    }
+
+    /// Unregisters and shuts down the account of `user_id`, returning it if there was one.
+    pub async fn remove(&self, user_id: &str) -> Option<Arc<AccountSession>> {
+        let account = self.accounts.write().await.remove(user_id)?;
+        account.shutdown().await;
+        Some(account)
+    }
+
+    pub async fn user_ids(&self) -> Vec<String> {
+        self.accounts.read().await.keys().cloned().collect()
+    }
+
+    pub async fn accounts(&self) -> Vec<Arc<AccountSession>> {
+        self.accounts.read().await.values().cloned().collect()
+    }
+
+    /// Shuts down and unregisters every account.
+    pub async fn shutdown_all(&self) {
+        let accounts: Vec<_> = self.accounts.write().await.drain().collect();
+        for (_, account) in accounts {
+            account.shutdown().await;
+        }
+    }
+}
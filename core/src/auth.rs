@@ -1,88 +1,174 @@
-use crate::client::{create_error_response, GLOBAL_API_CLIENT};
+use crate::account::{AccountSession, GLOBAL_ACCOUNTS};
+use crate::client::create_error_response;
 use crate::models::response::ApiResponse;
 use crate::models::{EitherTwoFactorAuthCodeType, LoginCredentials, TwoFactorVerifyResult};
 use crate::pipeline;
-use std::sync::LazyLock;
+use crate::services::session_service;
+use crate::session_store::PersistedSession;
+use crate::two_factor::{TwoFactorCode, TwoFactorMethod, TwoFactorProvider};
+use std::sync::{Arc, LazyLock};
 use tokio::sync::RwLock;
 use vrchatapi::apis::Error;
+use vrchatapi::models::EitherUserOrTwoFactor;
 
-pub static GLOBAL_PIPELINE_MANAGER: LazyLock<RwLock<Option<pipeline::PipelineManager>>> =
+/// The account behind the single-account functions in this module, as used by the
+/// desktop app and the worker. Further accounts are logged in with `login` directly.
+static GLOBAL_CURRENT_ACCOUNT: LazyLock<RwLock<Option<Arc<AccountSession>>>> =
     LazyLock::new(|| RwLock::new(None));
 
-fn get_cookies_path() -> String {
-    std::env::var("COOKIES_PATH").unwrap_or_else(|_| {
-        if std::path::Path::new("/app/cookies.json").exists() {
-            "/app/cookies.json".to_string()
-        } else {
-            "./cookies.json".to_string()
-        }
-    })
+/// A new account from `auth_login_and_get_current_user` that is still waiting for its
+/// two-factor code, kept apart until it logs in so the current one keeps running.
+static GLOBAL_PENDING_ACCOUNT: LazyLock<RwLock<Option<Arc<AccountSession>>>> =
+    LazyLock::new(|| RwLock::new(None));
+
+const MAX_TWO_FACTOR_ATTEMPTS: u32 = 3;
+
+/// The account of the latest successful `auth_login_and_get_current_user`.
+pub async fn current_account() -> Option<Arc<AccountSession>> {
+    GLOBAL_CURRENT_ACCOUNT.read().await.clone()
+}
+
+pub async fn current_user_id() -> Option<String> {
+    current_account().await?.user_id()
+}
+
+pub async fn pipeline_status() -> Option<pipeline::PipelineStatus> {
+    current_account().await?.pipeline_status().await
+}
+
+/// Stops the current account's pipeline, if any, waiting for queued events to be persisted.
+pub async fn shutdown_pipeline() {
+    if let Some(account) = current_account().await {
+        account.shutdown().await;
+    }
 }
 
+/// Why the stored session could not produce a new pipeline token.
+#[derive(Debug, thiserror::Error)]
+pub enum SessionRefreshError {
+    #[error("session expired, a new login is required")]
+    NeedsRelogin,
+    #[error("session expired, two-factor verification is required")]
+    NeedsTwoFactor,
+    #[error("failed to refresh session: {0}")]
+    Transient(String),
+}
+
+fn is_auth_rejection<T>(error: &Error<T>) -> bool {
+    matches!(error, Error::ResponseError(response) if response.status.as_u16() == 401)
+}
+
+/// Logs in the current account and starts its pipeline, asking `two_factor` for codes
+/// whenever VRChat requires them. The provider is kept for unattended re-logins on token
+/// expiry.
+///
+/// A first login starts a new account from the saved session at `session_path`, with or
+/// without credentials. It only replaces the current account, which is then shut down,
+/// once it has logged in; until then it waits for `auth_verify2_fa`.
 pub async fn auth_login_and_get_current_user(
     credentials: &Option<LoginCredentials>,
     is_first_login: &Option<bool>,
+    two_factor: Arc<dyn TwoFactorProvider>,
 ) -> ApiResponse<vrchatapi::models::EitherUserOrTwoFactor> {
-    let cookie_store_arc = if let Some(true) = is_first_login {
-        let cookies_path = get_cookies_path();
-        let cookie_store = {
-            if let Ok(file) = std::fs::File::open(&cookies_path) {
-                let reader = std::io::BufReader::new(file);
-                serde_json::from_reader(reader)
-                    .unwrap_or_else(|_| reqwest_cookie_store::CookieStore::new(None))
-            } else {
-                reqwest_cookie_store::CookieStore::new(None)
+    let previous = current_account().await;
+    let account = match previous.clone() {
+        Some(account) if is_first_login != &Some(true) => account,
+        _ => {
+            let session = PersistedSession::from_config();
+            match AccountSession::with_defaults(session, two_factor.clone()) {
+                Ok(account) => Arc::new(account),
+                Err(e) => {
+                    log::error!("Failed to build HTTP client: {}", e);
+                    return ApiResponse::simple_error(
+                        500,
+                        format!("Failed to build HTTP client: {}", e),
+                    );
+                }
             }
-        };
-        let cookie_store_arc =
-            std::sync::Arc::new(reqwest_cookie_store::CookieStoreMutex::new(cookie_store));
-
-        let mut global_client = GLOBAL_API_CLIENT.write().unwrap();
-        if let Some(creds) = credentials {
-            global_client.config.basic_auth =
-                Some((creds.username.clone(), creds.password.clone()));
-            log::info!("Updated basic auth for user: {}", creds.username);
         }
-        global_client.config.client = reqwest::Client::builder()
-            .cookie_provider(cookie_store_arc.clone())
-            .build()
-            .unwrap();
-
-        Some(cookie_store_arc)
-    } else {
-        None
     };
 
+    if let Some(creds) = credentials {
+        account.set_credentials(creds);
+    }
+    account.set_two_factor(two_factor);
+
+    let is_new = !previous
+        .as_ref()
+        .is_some_and(|previous| Arc::ptr_eq(previous, &account));
+    if is_new {
+        *GLOBAL_PENDING_ACCOUNT.write().await = Some(account.clone());
+    }
+
+    let response = login(&account).await;
+    if !is_new {
+        return response;
+    }
+    match response.data {
+        Some(EitherUserOrTwoFactor::CurrentUser(_)) => {}
+        Some(EitherUserOrTwoFactor::RequiresTwoFactorAuth(_)) => return response,
+        None => {
+            *GLOBAL_PENDING_ACCOUNT.write().await = None;
+            return response;
+        }
+    }
+
+    *GLOBAL_PENDING_ACCOUNT.write().await = None;
+    *GLOBAL_CURRENT_ACCOUNT.write().await = Some(account.clone());
+
+    if let Some(previous) = previous {
+        match (previous.user_id(), account.user_id()) {
+            (Some(previous_user_id), Some(user_id)) if previous_user_id == user_id => {
+                // Registering the new session already replaced this one.
+            }
+            (Some(previous_user_id), user_id) => {
+                log::info!(
+                    "Logged out of {} in favor of {}",
+                    previous_user_id,
+                    user_id.unwrap_or_default()
+                );
+                GLOBAL_ACCOUNTS.remove(&previous_user_id).await;
+            }
+            (None, _) => previous.shutdown().await,
+        }
+    }
+
+    response
+}
+
+/// Logs in `account`, registers it in `GLOBAL_ACCOUNTS` and starts its friend sync and
+/// pipeline. Two-factor codes come from the account's provider.
+pub async fn login(account: &Arc<AccountSession>) -> ApiResponse<EitherUserOrTwoFactor> {
+    let two_factor = account.two_factor();
+    let mut two_factor_attempts = 0;
+
     loop {
-        let client_config = {
-            let client = GLOBAL_API_CLIENT.read().unwrap();
-            client.config.clone()
-        };
+        let client_config = account.config();
 
         match vrchatapi::apis::authentication_api::get_current_user(&client_config).await {
             Ok(user_or_2fa) => match &user_or_2fa {
                 vrchatapi::models::EitherUserOrTwoFactor::CurrentUser(current_user) => {
                     println!("Login successful for user: {}", current_user.display_name);
-                    if let Some(cookie_store) = &cookie_store_arc {
-                        let mut writer = std::fs::File::create("cookies.json")
-                            .map(std::io::BufWriter::new)
-                            .unwrap();
-                        let store = cookie_store.lock().unwrap();
-                        serde_json::to_writer(&mut writer, &*store).unwrap();
-                        log::info!("Cookies saved successfully.");
-                    };
-                    match pipeline_auth().await {
+                    account.save_session();
+                    account.set_user_id(current_user.id.clone());
+                    // Stops an older session of the same user before this one starts.
+                    GLOBAL_ACCOUNTS.insert(account.clone()).await;
+                    account.start_friend_sync(current_user.id.clone());
+                    match pipeline_auth(account).await {
                         Ok(token) => {
-                            println!("token result: {:?}", token.clone());
-
-                            let mut manager = pipeline::PipelineManager::new(token.token.clone());
-                            manager.start().await;
-
+                            if let Err(e) =
+                                session_service::close_interrupted_sessions(Some(&current_user.id))
+                                    .await
                             {
-                                let mut global_manager = GLOBAL_PIPELINE_MANAGER.write().await;
-                                *global_manager = Some(manager);
+                                log::error!("Failed to close interrupted sessions: {}", e);
                             }
 
+                            println!("token result: {:?}", token.clone());
+
+                            let manager = pipeline::PipelineManager::new(token.token.clone())
+                                .with_account(account);
+                            account.start_pipeline(manager).await;
+
                             println!("Pipeline service started");
                         }
                         Err(e) => {
@@ -92,36 +178,34 @@ pub async fn auth_login_and_get_current_user(
 
                     return ApiResponse::success(user_or_2fa, Some("Login successful".to_string()));
                 }
-                vrchatapi::models::EitherUserOrTwoFactor::RequiresTwoFactorAuth(u) => {
-                    log::info!("2FA required: {:?}", u);
-                    println!("Please enter your 2FA code:");
-
-                    let mut guess = String::new();
-                    if std::io::stdin().read_line(&mut guess).is_err() {
+                vrchatapi::models::EitherUserOrTwoFactor::RequiresTwoFactorAuth(required) => {
+                    log::info!("2FA required: {:?}", required);
+                    if two_factor_attempts >= MAX_TWO_FACTOR_ATTEMPTS {
                         return ApiResponse::simple_error(
-                            500,
-                            "Failed to read 2FA code from stdin".to_string(),
+                            401,
+                            "2FA verification failed".to_string(),
                         );
                     }
+                    two_factor_attempts += 1;
 
-                    let verify_result = auth_verify2_fa(
-                        "2fa",
-                        EitherTwoFactorAuthCodeType::IsA(vrchatapi::models::TwoFactorAuthCode {
-                            code: guess.trim().to_string(),
-                        }),
-                    )
-                    .await;
+                    let methods = TwoFactorMethod::parse_all(&required.requires_two_factor_auth);
+                    let Some(code) = two_factor.provide_code(&methods).await else {
+                        return ApiResponse::success(
+                            user_or_2fa,
+                            Some("Two-factor authentication required".to_string()),
+                        );
+                    };
 
-                    if verify_result.success {
+                    if verify_two_factor_code(account, code).await {
                         log::info!("2FA verification successful. Retrying login...");
-                        continue;
                     } else {
-                        log::error!("2FA verification failed: {:?}", verify_result);
-                        return ApiResponse::simple_error(
-                            401,
-                            "2FA verification failed".to_string(),
+                        log::error!(
+                            "2FA verification failed (attempt {}/{})",
+                            two_factor_attempts,
+                            MAX_TWO_FACTOR_ATTEMPTS
                         );
                     }
+                    continue;
                 }
             },
             Err(e) => {
@@ -132,14 +216,30 @@ pub async fn auth_login_and_get_current_user(
     }
 }
 
+async fn verify_two_factor_code(account: &AccountSession, code: TwoFactorCode) -> bool {
+    let verify_type = code.method.verify_type();
+    let result = verify_two_factor(account, verify_type, code.into()).await;
+    result.success && result.data.is_some_and(|result| result.verified)
+}
+
+/// Completes the two-factor step of the current account's login.
 pub async fn auth_verify2_fa(
     two_fa_type: &str,
     code: EitherTwoFactorAuthCodeType,
 ) -> ApiResponse<TwoFactorVerifyResult> {
-    let client_config = {
-        let client = GLOBAL_API_CLIENT.read().unwrap();
-        client.config.clone()
-    };
+    let pending = GLOBAL_PENDING_ACCOUNT.read().await.clone();
+    match pending.or(current_account().await) {
+        Some(account) => verify_two_factor(&account, two_fa_type, code).await,
+        None => ApiResponse::simple_error(400, "No login in progress".to_string()),
+    }
+}
+
+pub async fn verify_two_factor(
+    account: &AccountSession,
+    two_fa_type: &str,
+    code: EitherTwoFactorAuthCodeType,
+) -> ApiResponse<TwoFactorVerifyResult> {
+    let client_config = account.config();
 
     match two_fa_type {
         "2fa" => {
@@ -153,6 +253,9 @@ pub async fn auth_verify2_fa(
                 {
                     Ok(res) => {
                         log::info!("2FA verification successful: {:?}", res);
+                        if res.verified {
+                            account.save_session();
+                        }
                         ApiResponse::success(
                             TwoFactorVerifyResult::from(res),
                             Some("2FA verification successful".to_string()),
@@ -178,10 +281,15 @@ pub async fn auth_verify2_fa(
                 )
                 .await
                 {
-                    Ok(res) => ApiResponse::success(
-                        TwoFactorVerifyResult::from(res),
-                        Some("Email 2FA verification successful".to_string()),
-                    ),
+                    Ok(res) => {
+                        if res.verified {
+                            account.save_session();
+                        }
+                        ApiResponse::success(
+                            TwoFactorVerifyResult::from(res),
+                            Some("Email 2FA verification successful".to_string()),
+                        )
+                    }
                     Err(e) => {
                         log::error!("Failed to verify 2FA email code: {:?}", e);
                         create_error_response(&e, "Failed to verify 2FA email code")
@@ -198,14 +306,13 @@ pub async fn auth_verify2_fa(
     }
 }
 
-pub async fn verify_auth() -> Result<
+pub async fn verify_auth(
+    account: &AccountSession,
+) -> Result<
     vrchatapi::models::VerifyAuthTokenResult,
     Error<vrchatapi::apis::authentication_api::VerifyAuthTokenError>,
 > {
-    let client_config = {
-        let client = GLOBAL_API_CLIENT.read().unwrap();
-        client.config.clone()
-    };
+    let client_config = account.config();
 
     match vrchatapi::apis::authentication_api::verify_auth_token(&client_config).await {
         Ok(result) => Ok(result),
@@ -216,14 +323,13 @@ pub async fn verify_auth() -> Result<
     }
 }
 
-pub async fn pipeline_auth() -> Result<
+pub async fn pipeline_auth(
+    account: &AccountSession,
+) -> Result<
     vrchatapi::models::VerifyAuthTokenResult,
     Error<vrchatapi::apis::authentication_api::VerifyAuthTokenError>,
 > {
-    let client_config = {
-        let client = GLOBAL_API_CLIENT.read().unwrap();
-        client.config.clone()
-    };
+    let client_config = account.config();
 
     match vrchatapi::apis::authentication_api::verify_auth_token(&client_config).await {
         Ok(result) => Ok(result),
@@ -233,3 +339,52 @@ pub async fn pipeline_auth() -> Result<
         }
     }
 }
+
+/// Fetches a fresh pipeline token for `account`, logging in again with its cookies and
+/// basic auth when the current auth token has been invalidated.
+pub async fn refresh_pipeline_token(
+    account: &AccountSession,
+) -> Result<String, SessionRefreshError> {
+    match pipeline_auth(account).await {
+        Ok(result) if result.ok => return Ok(result.token),
+        Ok(_) => {}
+        Err(e) if is_auth_rejection(&e) => {}
+        Err(e) => return Err(SessionRefreshError::Transient(e.to_string())),
+    }
+
+    log::info!("Pipeline token rejected, logging in again");
+
+    let client_config = account.config();
+
+    let mut two_factor_attempts = 0;
+    loop {
+        match vrchatapi::apis::authentication_api::get_current_user(&client_config).await {
+            Ok(EitherUserOrTwoFactor::CurrentUser(_)) => break,
+            Ok(EitherUserOrTwoFactor::RequiresTwoFactorAuth(required)) => {
+                let methods = TwoFactorMethod::parse_all(&required.requires_two_factor_auth);
+                let code = if two_factor_attempts < MAX_TWO_FACTOR_ATTEMPTS {
+                    account.two_factor().provide_code(&methods).await
+                } else {
+                    None
+                };
+                let Some(code) = code else {
+                    return Err(SessionRefreshError::NeedsTwoFactor);
+                };
+                two_factor_attempts += 1;
+                verify_two_factor_code(account, code).await;
+            }
+            Err(e) if is_auth_rejection(&e) => return Err(SessionRefreshError::NeedsRelogin),
+            Err(e) => return Err(SessionRefreshError::Transient(e.to_string())),
+        }
+    }
+
+    match pipeline_auth(account).await {
+        Ok(result) if result.ok => {
+            account.save_session();
+            Ok(result.token)
+        }
+        Ok(_) => Err(SessionRefreshError::NeedsRelogin),
+        Err(e) if is_auth_rejection(&e) => Err(SessionRefreshError::NeedsRelogin),
+        Err(e) => Err(SessionRefreshError::Transient(e.to_string())),
+    }
+}
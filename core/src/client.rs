@@ -3,6 +3,7 @@ use std::sync::{LazyLock, RwLock};
 use vrchatapi::apis::configuration::Configuration;
 use vrchatapi::apis::Error;
 
+/// Defaults every new `AccountSession` copies its client from, e.g. the API base URL.
 pub static GLOBAL_API_CLIENT: LazyLock<RwLock<VrcApiClient>> =
     LazyLock::new(|| RwLock::new(VrcApiClient::new()));
 
@@ -15,10 +16,13 @@ pub struct VrcApiClient {
 
 impl Default for VrcApiClient {
     fn default() -> Self {
-        let config = Configuration {
+        let mut config = Configuration {
             user_agent: Some(GLOBAL_USER_AGENT.clone()),
             ..Default::default()
         };
+        if let Ok(base_url) = std::env::var("VRCHAT_API_BASE_URL") {
+            config.base_path = base_url.trim_end_matches('/').to_string();
+        }
 
         Self { config }
     }
@@ -28,6 +32,18 @@ impl VrcApiClient {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Creates a client against `base_url` instead of `VRCHAT_API_BASE_URL` or production.
+    pub fn with_base_url(base_url: &str) -> Self {
+        let mut client = Self::default();
+        client.set_base_url(base_url);
+        client
+    }
+
+    /// Points every subsequent API call at `base_url`, e.g. `http://127.0.0.1:8080/api/1`.
+    pub fn set_base_url(&mut self, base_url: &str) {
+        self.config.base_path = base_url.trim_end_matches('/').to_string();
+    }
 }
 
 pub fn create_error_response<T, E>(error: &Error<E>, base_message: &str) -> ApiResponse<T> {
@@ -68,3 +84,14 @@ pub fn create_error_response<T, E>(error: &Error<E>, base_message: &str) -> ApiR
         error_details,
     )
 }
+
+/// Whether a failed API call is worth retrying: network errors, rate limits and server errors.
+pub fn is_transient_error<E>(error: &Error<E>) -> bool {
+    match error {
+        Error::Reqwest(_) | Error::Io(_) => true,
+        Error::ResponseError(response) => {
+            response.status.as_u16() == 429 || response.status.is_server_error()
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,85 @@
+//! Helpers for the environment variables that tune the worker.
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// `{prefix}_{name}`, or just `name` without a prefix, for settings that can be given once
+/// per account, e.g. `ALT_USERNAME`.
+pub fn prefixed(prefix: Option<&str>, name: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{}_{}", prefix, name),
+        None => name.to_string(),
+    }
+}
+
+/// Parses the environment variable `name`, or `None` when it is unset or invalid.
+pub fn env_var<T: FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
+}
+
+/// Parses the environment variable `name` as a positive number, falling back to `default`
+/// when it is unset, invalid or zero.
+pub fn positive_env_var<T: FromStr + PartialOrd + Default>(name: &str, default: T) -> T {
+    env_var(name)
+        .filter(|value| *value > T::default())
+        .unwrap_or(default)
+}
+
+/// `path` with `-<account>` appended to its file stem, so a file configured once for the
+/// process is not shared between accounts, e.g. `spill.jsonl` becomes `spill-usr_x.jsonl`.
+///
+/// Characters other than ASCII letters, digits, `-`, `_` and `.` are replaced with `_`.
+pub fn account_path(path: &Path, account: &str) -> PathBuf {
+    let account: String = account
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+    file_name.push("-");
+    file_name.push(account);
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positive_env_var_ignores_zero_and_garbage() {
+        std::env::remove_var("BOTAN_TEST_POSITIVE");
+        assert_eq!(positive_env_var("BOTAN_TEST_POSITIVE", 10u64), 10);
+        std::env::set_var("BOTAN_TEST_POSITIVE", "0");
+        assert_eq!(positive_env_var("BOTAN_TEST_POSITIVE", 10u64), 10);
+        std::env::set_var("BOTAN_TEST_POSITIVE", "soon");
+        assert_eq!(positive_env_var("BOTAN_TEST_POSITIVE", 10u64), 10);
+        std::env::set_var("BOTAN_TEST_POSITIVE", "3");
+        assert_eq!(positive_env_var("BOTAN_TEST_POSITIVE", 10u64), 3);
+    }
+
+    #[test]
+    fn account_path_suffixes_the_file_stem() {
+        assert_eq!(
+            account_path(Path::new("/app/data/ingest-spill.jsonl"), "usr_1234"),
+            Path::new("/app/data/ingest-spill-usr_1234.jsonl")
+        );
+        assert_eq!(
+            account_path(Path::new("cookies.json"), "Some One@mail/x"),
+            Path::new("cookies-Some_One_mail_x.json")
+        );
+        assert_eq!(
+            account_path(Path::new("record"), "usr_1"),
+            Path::new("record-usr_1")
+        );
+    }
+}
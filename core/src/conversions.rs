@@ -1,7 +1,11 @@
-use crate::entities::users;
+use crate::entities::{user_location_history, users};
+use crate::models::location::Location;
+use chrono::Utc;
 use sea_orm::entity::*;
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
-use vrchatapi::models::User;
+use serde_json::Value;
+use vrchatapi::models::{LimitedUser, User};
 
 #[derive(Deserialize, Debug)]
 #[serde(tag = "type", content = "content")]
@@ -14,6 +18,114 @@ pub enum WebsocketEvent {
     FriendOffline(FriendOfflineEvent),
     FriendUpdate(FriendUpdateEvent),
     FriendLocation(FriendLocationEvent),
+    Notification(NotificationEvent),
+    NotificationV2(NotificationV2Event),
+    SeeNotification(String),
+    HideNotification(String),
+    ResponseNotification(ResponseNotificationEvent),
+    UserUpdate(UserUpdateEvent),
+    UserLocation(UserLocationEvent),
+    UserBadgeAssigned(UserBadgeAssignedEvent),
+    ContentRefresh(ContentRefreshEvent),
+    GroupJoined(GroupJoinedEvent),
+    GroupLeft(GroupLeftEvent),
+    GroupMemberUpdated(GroupMemberUpdatedEvent),
+    InstanceQueueJoined(InstanceQueueJoinedEvent),
+    InstanceQueueReady(InstanceQueueReadyEvent),
+}
+
+/// The `type` of a pipeline frame, used to tell unknown events apart from malformed ones.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebsocketEventType {
+    FriendAdd,
+    FriendDelete,
+    FriendOnline,
+    FriendActive,
+    FriendOffline,
+    FriendUpdate,
+    FriendLocation,
+    Notification,
+    NotificationV2,
+    SeeNotification,
+    HideNotification,
+    ResponseNotification,
+    UserUpdate,
+    UserLocation,
+    UserBadgeAssigned,
+    ContentRefresh,
+    GroupJoined,
+    GroupLeft,
+    GroupMemberUpdated,
+    InstanceQueueJoined,
+    InstanceQueueReady,
+    #[serde(other)]
+    Unknown,
+}
+
+impl WebsocketEventType {
+    pub fn parse(event_type: &str) -> Self {
+        serde_json::from_value(Value::String(event_type.to_string()))
+            .unwrap_or(WebsocketEventType::Unknown)
+    }
+}
+
+impl WebsocketEvent {
+    /// Decodes an event from its frame parts, returning `None` for unknown event types.
+    ///
+    /// Content is decoded straight into the variant type so errors keep the path to the
+    /// offending field, which the adjacently tagged representation would lose.
+    pub fn from_parts(
+        event_type: &str,
+        content: &Value,
+    ) -> Result<Option<Self>, serde_path_to_error::Error<serde_json::Error>> {
+        fn decode<T: DeserializeOwned>(
+            content: &Value,
+        ) -> Result<T, serde_path_to_error::Error<serde_json::Error>> {
+            serde_path_to_error::deserialize(content)
+        }
+
+        let event = match WebsocketEventType::parse(event_type) {
+            WebsocketEventType::FriendAdd => WebsocketEvent::FriendAdd(decode(content)?),
+            WebsocketEventType::FriendDelete => WebsocketEvent::FriendDelete(decode(content)?),
+            WebsocketEventType::FriendOnline => WebsocketEvent::FriendOnline(decode(content)?),
+            WebsocketEventType::FriendActive => WebsocketEvent::FriendActive(decode(content)?),
+            WebsocketEventType::FriendOffline => WebsocketEvent::FriendOffline(decode(content)?),
+            WebsocketEventType::FriendUpdate => WebsocketEvent::FriendUpdate(decode(content)?),
+            WebsocketEventType::FriendLocation => WebsocketEvent::FriendLocation(decode(content)?),
+            WebsocketEventType::Notification => WebsocketEvent::Notification(decode(content)?),
+            WebsocketEventType::NotificationV2 => WebsocketEvent::NotificationV2(decode(content)?),
+            WebsocketEventType::SeeNotification => {
+                WebsocketEvent::SeeNotification(decode(content)?)
+            }
+            WebsocketEventType::HideNotification => {
+                WebsocketEvent::HideNotification(decode(content)?)
+            }
+            WebsocketEventType::ResponseNotification => {
+                WebsocketEvent::ResponseNotification(decode(content)?)
+            }
+            WebsocketEventType::UserUpdate => WebsocketEvent::UserUpdate(decode(content)?),
+            WebsocketEventType::UserLocation => WebsocketEvent::UserLocation(decode(content)?),
+            WebsocketEventType::UserBadgeAssigned => {
+                WebsocketEvent::UserBadgeAssigned(decode(content)?)
+            }
+            WebsocketEventType::ContentRefresh => WebsocketEvent::ContentRefresh(decode(content)?),
+            WebsocketEventType::GroupJoined => WebsocketEvent::GroupJoined(decode(content)?),
+            WebsocketEventType::GroupLeft => WebsocketEvent::GroupLeft(decode(content)?),
+            WebsocketEventType::GroupMemberUpdated => {
+                WebsocketEvent::GroupMemberUpdated(decode(content)?)
+            }
+            WebsocketEventType::InstanceQueueJoined => {
+                WebsocketEvent::InstanceQueueJoined(decode(content)?)
+            }
+            WebsocketEventType::InstanceQueueReady => {
+                WebsocketEvent::InstanceQueueReady(decode(content)?)
+            }
+            WebsocketEventType::Unknown => return Ok(None),
+        };
+
+        Ok(Some(event))
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -72,8 +184,209 @@ pub struct FriendLocationEvent {
     pub user: User,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationEvent {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    pub sender_user_id: String,
+    pub sender_username: Option<String>,
+    pub receiver_user_id: Option<String>,
+    #[serde(default)]
+    pub message: String,
+    #[serde(default)]
+    pub details: Value,
+    #[serde(rename = "created_at")]
+    pub created_at: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationV2Event {
+    pub id: String,
+    pub version: Option<i64>,
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    pub category: Option<String>,
+    pub is_system: Option<bool>,
+    pub sender_user_id: Option<String>,
+    pub sender_username: Option<String>,
+    pub receiver_user_id: Option<String>,
+    pub title: Option<String>,
+    pub message: Option<String>,
+    pub image_url: Option<String>,
+    pub link: Option<String>,
+    pub link_text: Option<String>,
+    #[serde(default)]
+    pub responses: Vec<Value>,
+    pub expires_at: Option<String>,
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub data: Value,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseNotificationEvent {
+    pub notification_id: String,
+    pub receiver_id: String,
+    pub response_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserUpdateEvent {
+    pub user_id: String,
+    pub user: UpdatedCurrentUser,
+}
+
+/// The subset of the logged-in user sent with `user-update`.
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedCurrentUser {
+    pub id: String,
+    pub display_name: String,
+    pub username: Option<String>,
+    pub bio: Option<String>,
+    pub status: Option<String>,
+    pub status_description: Option<String>,
+    pub pronouns: Option<String>,
+    pub current_avatar: Option<String>,
+    pub profile_pic_override: Option<String>,
+    pub user_icon: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserLocationEvent {
+    pub user_id: String,
+    pub location: Option<String>,
+    pub instance: Option<String>,
+    pub world_id: Option<String>,
+    pub traveling_to_location: Option<String>,
+    pub world: Option<Value>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserBadgeAssignedEvent {
+    pub badge: Value,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentRefreshEvent {
+    pub content_type: String,
+    pub file_id: Option<String>,
+    pub item_id: Option<String>,
+    pub item_type: Option<String>,
+    pub action_type: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupJoinedEvent {
+    pub group_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupLeftEvent {
+    pub group_id: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMemberUpdatedEvent {
+    pub member: UpdatedGroupMember,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdatedGroupMember {
+    pub id: Option<String>,
+    pub group_id: String,
+    pub user_id: String,
+    #[serde(default)]
+    pub role_ids: Vec<String>,
+    pub membership_status: Option<String>,
+    pub visibility: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceQueueJoinedEvent {
+    pub instance_location: String,
+    pub position: i64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceQueueReadyEvent {
+    pub instance_location: String,
+    pub expiry: Option<Value>,
+}
+
+impl FriendOnlineEvent {
+    pub fn parsed_location(&self) -> Option<Location> {
+        parse_location(self.location.as_deref())
+    }
+}
+
+impl FriendLocationEvent {
+    pub fn parsed_location(&self) -> Option<Location> {
+        parse_location(self.location.as_deref())
+    }
+
+    pub fn parsed_traveling_to_location(&self) -> Option<Location> {
+        parse_location(self.traveling_to_location.as_deref())
+    }
+}
+
+impl UserLocationEvent {
+    pub fn parsed_location(&self) -> Option<Location> {
+        parse_location(self.location.as_deref())
+    }
+}
+
+/// Parses an optional raw location string, treating empty and malformed values as unknown.
+pub fn parse_location(raw: Option<&str>) -> Option<Location> {
+    let raw = raw.filter(|s| !s.is_empty())?;
+    match raw.parse() {
+        Ok(location) => Some(location),
+        Err(e) => {
+            log::warn!("Failed to parse location {}: {}", raw, e);
+            None
+        }
+    }
+}
+
+pub fn location_history_model(
+    user_id: &str,
+    location: &Location,
+) -> user_location_history::ActiveModel {
+    let instance = location.instance();
+
+    user_location_history::ActiveModel {
+        user_id: Set(user_id.to_string()),
+        location: Set(Some(location.to_string())),
+        world_id: Set(location.world_id().map(str::to_string)),
+        instance_name: Set(instance.and_then(|i| i.instance_name.clone())),
+        access_type: Set(location.access_type().map(|a| a.to_string())),
+        region: Set(location.region().map(str::to_string)),
+        ..Default::default()
+    }
+}
+
 impl From<User> for users::ActiveModel {
     fn from(api_user: User) -> Self {
+        let raw_data = serde_json::to_value(&api_user)
+            .inspect_err(|e| log::warn!("Failed to serialize user {}: {}", api_user.id, e))
+            .ok();
+
         Self {
             id: Set(api_user.id),
             username: Set(api_user.username),
@@ -84,6 +397,35 @@ impl From<User> for users::ActiveModel {
             bio: Set(api_user.bio),
             status: Set(api_user.status.to_string()),
             status_description: Set(api_user.status_description),
+            profile_pic_override: Set(Some(api_user.profile_pic_override)),
+            user_icon: Set(Some(api_user.user_icon)),
+            last_api_update_at: Set(Some(Utc::now().into())),
+            raw_data: Set(raw_data),
+
+            ..Default::default()
+        }
+    }
+}
+
+impl From<LimitedUser> for users::ActiveModel {
+    fn from(api_user: LimitedUser) -> Self {
+        let raw_data = serde_json::to_value(&api_user)
+            .inspect_err(|e| log::warn!("Failed to serialize user {}: {}", api_user.id, e))
+            .ok();
+
+        Self {
+            id: Set(api_user.id),
+            username: Set(api_user.username),
+            display_name: Set(api_user.display_name),
+            is_friend: Set(api_user.is_friend),
+            last_login: Set(api_user.last_login.flatten().unwrap_or_default()),
+            pronouns: Set(api_user.pronouns.unwrap_or_default()),
+            bio: Set(api_user.bio.unwrap_or_default()),
+            status: Set(api_user.status.to_string()),
+            status_description: Set(api_user.status_description),
+            profile_pic_override: Set(api_user.profile_pic_override),
+            user_icon: Set(api_user.user_icon),
+            raw_data: Set(raw_data),
 
             ..Default::default()
         }
@@ -29,9 +29,14 @@ pub async fn init_database() -> Result<(), DbErr> {
             "sqlite://./botan.db?mode=rwc".to_string()
         });
 
-    log::info!("Connecting to database: {}", &database_url);
+    init_database_with_url(&database_url).await
+}
+
+/// Connects to `database_url`, runs migrations and installs it as the global connection.
+pub async fn init_database_with_url(database_url: &str) -> Result<(), DbErr> {
+    log::info!("Connecting to database: {}", database_url);
 
-    let db = Database::connect(&database_url).await?;
+    let db = Database::connect(database_url).await?;
 
     match db.ping().await {
         Ok(_) => log::info!("Database connection established and tested"),
@@ -2,7 +2,10 @@
 
 pub mod prelude;
 
+pub mod event_dead_letters;
+pub mod friend_sessions;
 pub mod friendships;
+pub mod pipeline_events;
 pub mod user_attribute_history;
 pub mod user_location_history;
 pub mod users;
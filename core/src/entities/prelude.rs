@@ -1,6 +1,9 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.12
 
+pub use super::event_dead_letters::Entity as EventDeadLetters;
+pub use super::friend_sessions::Entity as FriendSessions;
 pub use super::friendships::Entity as Friendships;
+pub use super::pipeline_events::Entity as PipelineEvents;
 pub use super::user_attribute_history::Entity as UserAttributeHistory;
 pub use super::user_location_history::Entity as UserLocationHistory;
 pub use super::users::Entity as Users;
@@ -12,6 +12,10 @@ pub struct Model {
     pub location: Option<String>,
     pub world_id: Option<String>,
     pub recorded_at: DateTimeWithTimeZone,
+    pub instance_name: Option<String>,
+    pub access_type: Option<String>,
+    pub region: Option<String>,
+    pub observer_user_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
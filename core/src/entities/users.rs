@@ -30,6 +30,8 @@ pub struct Model {
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::friend_sessions::Entity")]
+    FriendSessions,
     #[sea_orm(has_many = "super::friendships::Entity")]
     Friendships,
     #[sea_orm(has_many = "super::user_attribute_history::Entity")]
@@ -38,6 +40,12 @@ pub enum Relation {
     UserLocationHistory,
 }
 
+impl Related<super::friend_sessions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::FriendSessions.def()
+    }
+}
+
 impl Related<super::friendships::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Friendships.def()
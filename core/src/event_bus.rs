@@ -0,0 +1,146 @@
+use crate::config;
+use crate::models::location::Location;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+const DEFAULT_EVENT_BUS_CAPACITY: usize = 1024;
+
+pub static GLOBAL_EVENT_BUS: LazyLock<EventBus> =
+    LazyLock::new(|| EventBus::new(event_bus_capacity()));
+
+/// Events published inside botan_core for independent consumers such as persistence,
+/// the Tauri app, notification rules and exporters.
+///
+/// `account_user_id` is the logged-in account whose pipeline the event came from, so
+/// consumers can tell accounts apart when several are watched at once.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DomainEvent {
+    /// A pipeline frame with its `content` already decoded.
+    #[serde(rename_all = "camelCase")]
+    PipelineFrame {
+        account_user_id: Option<String>,
+        event_type: String,
+        content: Value,
+        received_at: DateTime<Utc>,
+    },
+    /// A friend's presence changed, after pending-offline consolidation.
+    #[serde(rename_all = "camelCase")]
+    PresenceChanged {
+        account_user_id: Option<String>,
+        user_id: String,
+        state: PresenceState,
+        at: DateTime<Utc>,
+    },
+    #[serde(rename_all = "camelCase")]
+    LocationChanged {
+        account_user_id: Option<String>,
+        user_id: String,
+        location: Location,
+    },
+    #[serde(rename_all = "camelCase")]
+    FriendAdded {
+        account_user_id: Option<String>,
+        user_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    FriendRemoved {
+        account_user_id: Option<String>,
+        user_id: String,
+    },
+    #[serde(rename_all = "camelCase")]
+    PipelineConnected { account_user_id: Option<String> },
+    #[serde(rename_all = "camelCase")]
+    PipelineDisconnected { account_user_id: Option<String> },
+    /// The pipeline token expired and the session could not be refreshed on its own.
+    #[serde(rename_all = "camelCase")]
+    PipelineAuthRequired {
+        account_user_id: Option<String>,
+        needs_two_factor: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceState {
+    Online,
+    Active,
+    Offline,
+}
+
+/// Broadcast bus for `DomainEvent`s; slow subscribers skip events instead of blocking publishers.
+pub struct EventBus {
+    sender: broadcast::Sender<DomainEvent>,
+    lagged: Mutex<HashMap<String, u64>>,
+}
+
+/// Capacity of the bus before slow subscribers start lagging, from `EVENT_BUS_CAPACITY`.
+pub fn event_bus_capacity() -> usize {
+    config::positive_env_var("EVENT_BUS_CAPACITY", DEFAULT_EVENT_BUS_CAPACITY)
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            sender,
+            lagged: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Publishes `event` to every current subscriber, returning how many received it.
+    pub fn publish(&self, event: DomainEvent) -> usize {
+        self.sender.send(event).unwrap_or(0)
+    }
+
+    /// Subscribes to events published from now on; `name` identifies the subscriber in lag reports.
+    pub fn subscribe(&self, name: &str) -> Subscription<'_> {
+        Subscription {
+            name: name.to_string(),
+            receiver: self.sender.subscribe(),
+            bus: self,
+        }
+    }
+
+    /// Total events each subscriber has skipped because it fell behind.
+    pub fn lag_report(&self) -> HashMap<String, u64> {
+        self.lagged.lock().unwrap().clone()
+    }
+
+    fn record_lag(&self, name: &str, skipped: u64) {
+        let mut lagged = self.lagged.lock().unwrap();
+        *lagged.entry(name.to_string()).or_default() += skipped;
+    }
+}
+
+pub struct Subscription<'a> {
+    name: String,
+    receiver: broadcast::Receiver<DomainEvent>,
+    bus: &'a EventBus,
+}
+
+impl Subscription<'_> {
+    /// Waits for the next event, reporting and skipping over any overflow.
+    ///
+    /// Returns `None` once the bus is gone.
+    pub async fn recv(&mut self) -> Option<DomainEvent> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => return Some(event),
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!(
+                        "Event bus subscriber {} lagged behind, skipped {} event(s)",
+                        self.name,
+                        skipped
+                    );
+                    self.bus.record_lag(&self.name, skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use crate::client;
+use crate::config;
+use crate::reconnect::{self, ReconnectPolicy};
+use crate::services::{friendship_service, user_service};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use tokio::time::{interval_at, Instant};
+use vrchatapi::apis::configuration::Configuration;
+use vrchatapi::apis::friends_api::GetFriendsError;
+use vrchatapi::apis::Error;
+use vrchatapi::models::LimitedUser;
+
+const FRIENDS_PAGE_SIZE: i32 = 100;
+const FRIENDS_PAGE_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_FRIEND_SYNC_INTERVAL_SECS: u64 = 1800;
+/// Requested syncs closer than this to the previous one are skipped.
+const MIN_FRIEND_SYNC_GAP: Duration = Duration::from_secs(60);
+
+/// Pages through `/auth/user/friends` for both online and offline friends.
+pub async fn fetch_all_friends(
+    client_config: &Configuration,
+) -> Result<Vec<LimitedUser>, Error<GetFriendsError>> {
+    let retry_policy = ReconnectPolicy::rest();
+
+    let mut friends = Vec::new();
+    for offline in [false, true] {
+        let mut offset = 0;
+        loop {
+            let page = reconnect::retry(
+                &retry_policy,
+                FRIENDS_PAGE_MAX_ATTEMPTS,
+                client::is_transient_error,
+                || {
+                    vrchatapi::apis::friends_api::get_friends(
+                        client_config,
+                        Some(offset),
+                        Some(FRIENDS_PAGE_SIZE),
+                        Some(offline),
+                    )
+                },
+            )
+            .await
+            .inspect_err(|e| log::error!("Failed to fetch friends page: {:?}", e))?;
+
+            let page_len = page.len() as i32;
+            friends.extend(page);
+            if page_len < FRIENDS_PAGE_SIZE {
+                break;
+            }
+            offset += page_len;
+        }
+    }
+
+    log::info!("Fetched {} friends from API", friends.len());
+
+    Ok(friends)
+}
+
+/// Fetches the full friend list, upserts every friend and reconciles `friendships` against it.
+pub async fn sync_friend_list(
+    client_config: &Configuration,
+    owner_user_id: &str,
+) -> anyhow::Result<()> {
+    let friends = fetch_all_friends(client_config).await?;
+    user_service::sync_friends(owner_user_id, &friends).await?;
+    friendship_service::reconcile_friendships(owner_user_id, &friends).await?;
+    Ok(())
+}
+
+/// How often the friend list is re-synced, from `FRIEND_SYNC_INTERVAL_SECS`.
+pub fn friend_sync_interval() -> Duration {
+    Duration::from_secs(config::positive_env_var(
+        "FRIEND_SYNC_INTERVAL_SECS",
+        DEFAULT_FRIEND_SYNC_INTERVAL_SECS,
+    ))
+}
+
+/// Background friend list sync of one account, stopped when dropped.
+pub struct FriendSync {
+    task: JoinHandle<()>,
+    requested: Arc<Notify>,
+}
+
+impl FriendSync {
+    /// Starts syncing the friends of `owner_user_id` through `client_config`.
+    ///
+    /// The first sync runs immediately, then on every interval tick and whenever
+    /// `request` is called.
+    pub fn start(client_config: Configuration, owner_user_id: String) -> Self {
+        let period = friend_sync_interval();
+        let requested = Arc::new(Notify::new());
+
+        let task = {
+            let requested = requested.clone();
+            tokio::spawn(async move {
+                let mut ticker = interval_at(Instant::now() + period, period);
+                let mut last_sync: Option<Instant> = None;
+
+                loop {
+                    if last_sync.is_some_and(|t| t.elapsed() < MIN_FRIEND_SYNC_GAP) {
+                        log::debug!("Skipping friend sync, last one finished recently");
+                    } else {
+                        if let Err(e) = sync_friend_list(&client_config, &owner_user_id).await {
+                            log::error!("Friend list sync for {} failed: {}", owner_user_id, e);
+                        }
+                        last_sync = Some(Instant::now());
+                    }
+
+                    tokio::select! {
+                        _ = ticker.tick() => {}
+                        _ = requested.notified() => {}
+                    }
+                }
+            })
+        };
+
+        log::info!("Friend sync scheduled every {}s", period.as_secs());
+
+        Self { task, requested }
+    }
+
+    /// Asks for a sync as soon as possible, e.g. after a pipeline reconnect.
+    pub fn request(&self) {
+        self.requested.notify_one();
+    }
+}
+
+impl Drop for FriendSync {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
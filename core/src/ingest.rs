@@ -0,0 +1,469 @@
+use crate::config;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+const DEFAULT_INGEST_QUEUE_CAPACITY: usize = 1024;
+const DEFAULT_INGEST_BATCH_SIZE: usize = 100;
+const DEFAULT_INGEST_SPILL_PATH: &str = "./ingest-spill.jsonl";
+
+/// A decoded pipeline frame waiting to be persisted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestFrame {
+    pub event_type: String,
+    pub content: Value,
+    pub received_at: DateTime<Utc>,
+}
+
+/// What `IngestQueue::push` does once the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackpressurePolicy {
+    /// Wait for the persistence worker to make room, which also stops reading the socket.
+    Block,
+    /// Discard the oldest queued frame to make room for the new one.
+    DropOldest,
+    /// Append frames to a JSONL file on disk until the worker has caught up.
+    Spill,
+}
+
+impl BackpressurePolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "block" => Some(BackpressurePolicy::Block),
+            "drop-oldest" | "drop_oldest" => Some(BackpressurePolicy::DropOldest),
+            "spill" => Some(BackpressurePolicy::Spill),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IngestConfig {
+    pub capacity: usize,
+    pub batch_size: usize,
+    pub policy: BackpressurePolicy,
+    pub spill_path: PathBuf,
+}
+
+impl IngestConfig {
+    /// Reads `INGEST_QUEUE_CAPACITY`, `INGEST_BATCH_SIZE`, `INGEST_BACKPRESSURE`
+    /// (`block`, `drop-oldest` or `spill`) and `INGEST_SPILL_PATH`.
+    pub fn from_env() -> Self {
+        let capacity =
+            config::positive_env_var("INGEST_QUEUE_CAPACITY", DEFAULT_INGEST_QUEUE_CAPACITY);
+        let batch_size = config::positive_env_var("INGEST_BATCH_SIZE", DEFAULT_INGEST_BATCH_SIZE);
+        let policy = match std::env::var("INGEST_BACKPRESSURE") {
+            Ok(value) => BackpressurePolicy::parse(&value).unwrap_or_else(|| {
+                log::warn!("Unknown INGEST_BACKPRESSURE {}, using block", value);
+                BackpressurePolicy::Block
+            }),
+            Err(_) => BackpressurePolicy::Block,
+        };
+        let spill_path = std::env::var("INGEST_SPILL_PATH")
+            .unwrap_or_else(|_| DEFAULT_INGEST_SPILL_PATH.to_string())
+            .into();
+
+        Self {
+            capacity,
+            batch_size,
+            policy,
+            spill_path,
+        }
+    }
+
+    /// Gives `account_user_id` a spill file of its own, so a queue never picks up frames
+    /// spilled by another account's pipeline.
+    pub fn for_account(mut self, account_user_id: Option<&str>) -> Self {
+        if let Some(account_user_id) = account_user_id {
+            self.spill_path = config::account_path(&self.spill_path, account_user_id);
+        }
+        self
+    }
+}
+
+struct QueueState {
+    frames: VecDeque<IngestFrame>,
+    /// Frames read back from the spill file, newer than anything in `frames`.
+    replay: VecDeque<IngestFrame>,
+    /// While set, new frames go to the spill file so they stay ordered behind spilled ones.
+    spilling: bool,
+    /// How far the spill file has been read into `replay`. The file is only removed once
+    /// everything up to here has been archived.
+    spill_offset: u64,
+    /// The batch last handed out holds replayed frames and awaits `finish_batch`.
+    replay_in_flight: bool,
+    /// A replayed batch failed to archive, so the spill file is kept for the next run.
+    replay_failed: bool,
+    /// Set on shutdown; `pop_batch` then drains memory and returns an empty batch.
+    closed: bool,
+    dropped: u64,
+    spilled: u64,
+}
+
+/// Bounded queue between the pipeline reader and the persistence worker.
+pub struct IngestQueue {
+    config: IngestConfig,
+    state: Mutex<QueueState>,
+    not_empty: Notify,
+    not_full: Notify,
+}
+
+impl IngestQueue {
+    pub fn new(config: IngestConfig) -> Self {
+        // Frames spilled by a previous run are replayed before anything new.
+        let spilling = config.spill_path.exists();
+        if spilling {
+            log::info!(
+                "Found spilled frames at {}, replaying them first",
+                config.spill_path.display()
+            );
+        }
+
+        Self {
+            config,
+            state: Mutex::new(QueueState {
+                frames: VecDeque::new(),
+                replay: VecDeque::new(),
+                spilling,
+                spill_offset: 0,
+                replay_in_flight: false,
+                replay_failed: false,
+                closed: false,
+                dropped: 0,
+                spilled: 0,
+            }),
+            not_empty: Notify::new(),
+            not_full: Notify::new(),
+        }
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.config.batch_size
+    }
+
+    /// Frames currently held in memory, excluding anything spilled to disk.
+    pub fn len(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state.frames.len() + state.replay.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total frames discarded by the drop-oldest policy.
+    pub fn dropped(&self) -> u64 {
+        self.state.lock().unwrap().dropped
+    }
+
+    /// Total frames written to the spill file.
+    pub fn spilled(&self) -> u64 {
+        self.state.lock().unwrap().spilled
+    }
+
+    /// Enqueues `frame`, applying the backpressure policy when the queue is full.
+    pub async fn push(&self, frame: IngestFrame) {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+
+                if state.spilling {
+                    self.spill(&mut state, &frame);
+                    self.not_empty.notify_one();
+                    return;
+                }
+
+                if state.frames.len() < self.config.capacity {
+                    state.frames.push_back(frame);
+                    self.not_empty.notify_one();
+                    return;
+                }
+
+                match self.config.policy {
+                    BackpressurePolicy::DropOldest => {
+                        if let Some(dropped) = state.frames.pop_front() {
+                            state.dropped += 1;
+                            log::warn!(
+                                "Ingest queue full, dropped {} event from {} ({} dropped so far)",
+                                dropped.event_type,
+                                dropped.received_at,
+                                state.dropped
+                            );
+                        }
+                        state.frames.push_back(frame);
+                        self.not_empty.notify_one();
+                        return;
+                    }
+                    BackpressurePolicy::Spill => {
+                        log::warn!(
+                            "Ingest queue full, spilling to {}",
+                            self.config.spill_path.display()
+                        );
+                        state.spilling = true;
+                        self.spill(&mut state, &frame);
+                        self.not_empty.notify_one();
+                        return;
+                    }
+                    BackpressurePolicy::Block => {}
+                }
+            }
+
+            log::warn!("Ingest queue full, waiting for the persistence worker");
+            self.not_full.notified().await;
+        }
+    }
+
+    /// Stops the consumer once everything held in memory has been handed out.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_one();
+    }
+
+    /// Waits for frames and returns up to the configured batch size, oldest first. Each
+    /// batch is to be followed by `finish_batch` before the next one is taken.
+    ///
+    /// Returns an empty batch only after `close`, once nothing is left in memory.
+    pub async fn pop_batch(&self) -> Vec<IngestFrame> {
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+
+                if !state.frames.is_empty() {
+                    let count = state.frames.len().min(self.config.batch_size);
+                    let batch = state.frames.drain(..count).collect();
+                    self.not_full.notify_one();
+                    return batch;
+                }
+
+                if !state.replay.is_empty() {
+                    let count = state.replay.len().min(self.config.batch_size);
+                    state.replay_in_flight = true;
+                    return state.replay.drain(..count).collect();
+                }
+
+                if state.closed {
+                    // Frames still on disk are replayed by the next queue on startup.
+                    return Vec::new();
+                }
+
+                if state.spilling {
+                    // Producers only touch the spill file under this lock, so nothing is
+                    // appended halfway through a read.
+                    match self.take_spilled(&mut state) {
+                        Ok(frames) if !frames.is_empty() => {
+                            log::info!("Replaying {} spilled frame(s)", frames.len());
+                            state.replay = frames;
+                            continue;
+                        }
+                        Ok(_) => state.spilling = false,
+                        Err(e) => {
+                            log::error!("Failed to read ingest spill file: {}", e);
+                            state.spilling = false;
+                        }
+                    }
+                }
+            }
+
+            self.not_empty.notified().await;
+        }
+    }
+
+    /// Reports whether the batch last returned by `pop_batch` was archived. Once every frame
+    /// read back from the spill file has been, the file is removed; until then a crash
+    /// replays it again rather than losing frames.
+    pub fn finish_batch(&self, archived: bool) {
+        let mut state = self.state.lock().unwrap();
+        if !std::mem::take(&mut state.replay_in_flight) {
+            return;
+        }
+        if !archived {
+            log::warn!(
+                "Replayed frames were not archived, keeping {} for the next run",
+                self.config.spill_path.display()
+            );
+            state.replay_failed = true;
+            return;
+        }
+        if state.replay_failed || !state.replay.is_empty() {
+            return;
+        }
+
+        // Frames spilled since the last read are replayed, and the file removed, later.
+        let fully_read = std::fs::metadata(&self.config.spill_path)
+            .is_ok_and(|metadata| metadata.len() <= state.spill_offset);
+        if fully_read {
+            match std::fs::remove_file(&self.config.spill_path) {
+                Ok(()) => state.spill_offset = 0,
+                Err(e) => log::error!("Failed to remove ingest spill file: {}", e),
+            }
+        }
+    }
+
+    fn spill(&self, state: &mut QueueState, frame: &IngestFrame) {
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.spill_path)
+            .and_then(|mut file| {
+                let line = serde_json::to_string(frame)?;
+                writeln!(file, "{}", line)
+            });
+
+        match result {
+            Ok(()) => state.spilled += 1,
+            Err(e) => {
+                // Keeping the frame in memory, even past capacity and out of order, beats
+                // losing it.
+                log::error!("Failed to spill frame, keeping it in memory: {}", e);
+                state.frames.push_back(frame.clone());
+            }
+        }
+    }
+
+    /// Reads the frames spilled since the last call, leaving the file in place.
+    fn take_spilled(&self, state: &mut QueueState) -> std::io::Result<VecDeque<IngestFrame>> {
+        let mut file = match File::open(&self.config.spill_path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(VecDeque::new()),
+            Err(e) => return Err(e),
+        };
+
+        file.seek(SeekFrom::Start(state.spill_offset))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        state.spill_offset += contents.len() as u64;
+
+        let mut frames = VecDeque::new();
+        for line in contents.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(frame) => frames.push_back(frame),
+                Err(e) => log::error!("Skipping unreadable spilled frame: {}", e),
+            }
+        }
+
+        Ok(frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn frame(n: u64) -> IngestFrame {
+        IngestFrame {
+            event_type: "friend-location".to_string(),
+            content: json!({ "n": n }),
+            received_at: Utc::now(),
+        }
+    }
+
+    fn numbers(batch: &[IngestFrame]) -> Vec<u64> {
+        batch
+            .iter()
+            .map(|f| f.content["n"].as_u64().unwrap())
+            .collect()
+    }
+
+    fn queue(name: &str, capacity: usize, policy: BackpressurePolicy) -> IngestQueue {
+        let spill_path = std::env::temp_dir().join(format!(
+            "botan-ingest-{}-{}.jsonl",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&spill_path);
+        IngestQueue::new(IngestConfig {
+            capacity,
+            batch_size: 2,
+            policy,
+            spill_path,
+        })
+    }
+
+    #[tokio::test]
+    async fn block_waits_for_room() {
+        let queue = Arc::new(queue("block", 1, BackpressurePolicy::Block));
+        queue.push(frame(0)).await;
+
+        let pusher = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.push(frame(1)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!pusher.is_finished());
+        assert_eq!(queue.len(), 1);
+
+        assert_eq!(numbers(&queue.pop_batch().await), vec![0]);
+        pusher.await.unwrap();
+        assert_eq!(numbers(&queue.pop_batch().await), vec![1]);
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_discards_the_head() {
+        let queue = queue("drop-oldest", 2, BackpressurePolicy::DropOldest);
+        for n in 0..4 {
+            queue.push(frame(n)).await;
+        }
+
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(numbers(&queue.pop_batch().await), vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn spill_keeps_the_file_until_replay_is_archived() {
+        let queue = queue("spill", 2, BackpressurePolicy::Spill);
+        for n in 0..5 {
+            queue.push(frame(n)).await;
+        }
+        assert_eq!(queue.spilled(), 3);
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(numbers(&queue.pop_batch().await), vec![0, 1]);
+        queue.finish_batch(true);
+        assert_eq!(numbers(&queue.pop_batch().await), vec![2, 3]);
+        queue.finish_batch(true);
+        assert_eq!(numbers(&queue.pop_batch().await), vec![4]);
+        assert!(queue.config.spill_path.exists());
+
+        queue.finish_batch(false);
+        assert!(queue.config.spill_path.exists());
+        let _ = std::fs::remove_file(&queue.config.spill_path);
+    }
+
+    #[tokio::test]
+    async fn spilled_frames_replay_in_order() {
+        let queue = queue("spill-order", 2, BackpressurePolicy::Spill);
+        let mut seen = Vec::new();
+        for n in 0..4 {
+            queue.push(frame(n)).await;
+        }
+
+        seen.extend(numbers(&queue.pop_batch().await));
+        queue.finish_batch(true);
+        // Still behind the spilled frames, so these go to disk as well.
+        for n in 4..6 {
+            queue.push(frame(n)).await;
+        }
+        while seen.len() < 6 {
+            seen.extend(numbers(&queue.pop_batch().await));
+            queue.finish_batch(true);
+        }
+        assert_eq!(seen, (0..6).collect::<Vec<_>>());
+
+        // Drained, so the next pop reads nothing back and the queue is in memory again.
+        queue.close();
+        assert!(queue.pop_batch().await.is_empty());
+        assert!(!queue.config.spill_path.exists());
+    }
+}
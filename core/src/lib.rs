@@ -1,11 +1,25 @@
+pub mod account;
 pub mod auth;
 pub mod client;
+pub mod config;
 pub mod conversions;
 pub mod database;
 pub mod entities;
+pub mod event_bus;
+pub mod friends;
+pub mod ingest;
 pub mod models;
 mod pipeline;
+pub mod reconnect;
+pub mod recording;
+pub mod secrets;
 pub mod services;
+pub mod session_store;
+#[cfg(feature = "test-support")]
+pub mod testing;
+pub mod totp;
+pub mod two_factor;
 
+pub use pipeline::{pipeline_url, PipelineAuthState, PipelineManager, PipelineStatus};
 pub use vrchatapi::apis as vrchatapi_apis;
 pub use vrchatapi::models as vrchatapi_models;
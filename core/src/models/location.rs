@@ -0,0 +1,373 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A parsed VRChat location string, e.g.
+/// `wrld_x:12345~hidden(usr_y)~region(jp)~nonce(...)`.
+///
+/// Instance tags are re-serialized in the order VRChat emits them: access
+/// type, `groupAccessType`, `canRequestInvite`, `region`, `nonce`, `strict`,
+/// followed by any tags this parser does not know about. An unknown tag in
+/// front of known ones therefore moves to the end, so such a string does not
+/// round-trip byte for byte, although it parses back to an equal value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Location {
+    Offline,
+    Private,
+    Traveling,
+    Instance(InstanceLocation),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceLocation {
+    pub world_id: String,
+    pub instance_name: Option<String>,
+    pub access_type: AccessType,
+    pub owner_id: Option<String>,
+    pub group_access_type: Option<String>,
+    pub region: Option<String>,
+    pub nonce: Option<String>,
+    pub can_request_invite: bool,
+    pub strict: bool,
+    pub extra_tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessType {
+    Public,
+    Friends,
+    Hidden,
+    Private,
+    Group,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum LocationParseError {
+    #[error("location string is empty")]
+    Empty,
+    #[error("missing world id in location: {0}")]
+    MissingWorldId(String),
+    #[error("missing instance name in location: {0}")]
+    MissingInstanceName(String),
+    #[error("malformed tag `{tag}` in location: {location}")]
+    MalformedTag { tag: String, location: String },
+}
+
+impl AccessType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccessType::Public => "public",
+            AccessType::Friends => "friends",
+            AccessType::Hidden => "hidden",
+            AccessType::Private => "private",
+            AccessType::Group => "group",
+        }
+    }
+}
+
+impl fmt::Display for AccessType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Location {
+    pub fn instance(&self) -> Option<&InstanceLocation> {
+        match self {
+            Location::Instance(instance) => Some(instance),
+            _ => None,
+        }
+    }
+
+    pub fn world_id(&self) -> Option<&str> {
+        self.instance().map(|i| i.world_id.as_str())
+    }
+
+    pub fn region(&self) -> Option<&str> {
+        self.instance().and_then(|i| i.region.as_deref())
+    }
+
+    pub fn access_type(&self) -> Option<AccessType> {
+        self.instance().map(|i| i.access_type)
+    }
+
+    pub fn is_offline(&self) -> bool {
+        matches!(self, Location::Offline)
+    }
+
+    pub fn is_private(&self) -> bool {
+        matches!(self, Location::Private)
+    }
+
+    pub fn is_traveling(&self) -> bool {
+        matches!(self, Location::Traveling)
+    }
+}
+
+impl InstanceLocation {
+    fn parse(location: &str) -> Result<Self, LocationParseError> {
+        let (world_id, rest) = match location.split_once(':') {
+            Some((world_id, rest)) => (world_id, Some(rest)),
+            None => (location, None),
+        };
+        if world_id.is_empty() || world_id.starts_with('~') {
+            return Err(LocationParseError::MissingWorldId(location.to_string()));
+        }
+        // Tags are only valid after an instance name.
+        if world_id.contains(['~', '(', ')']) {
+            return Err(LocationParseError::MissingInstanceName(
+                location.to_string(),
+            ));
+        }
+
+        let mut instance = InstanceLocation {
+            world_id: world_id.to_string(),
+            instance_name: None,
+            access_type: AccessType::Public,
+            owner_id: None,
+            group_access_type: None,
+            region: None,
+            nonce: None,
+            can_request_invite: false,
+            strict: false,
+            extra_tags: Vec::new(),
+        };
+
+        let Some(rest) = rest else {
+            return Ok(instance);
+        };
+
+        let mut segments = rest.split('~');
+        match segments.next() {
+            Some(name) if !name.is_empty() => instance.instance_name = Some(name.to_string()),
+            _ => {
+                return Err(LocationParseError::MissingInstanceName(
+                    location.to_string(),
+                ))
+            }
+        }
+
+        for tag in segments {
+            let (key, value) = split_tag(tag).ok_or_else(|| LocationParseError::MalformedTag {
+                tag: tag.to_string(),
+                location: location.to_string(),
+            })?;
+
+            match (key, value) {
+                ("hidden", Some(owner)) => instance.set_access(AccessType::Hidden, owner),
+                ("friends", Some(owner)) => instance.set_access(AccessType::Friends, owner),
+                ("private", Some(owner)) => instance.set_access(AccessType::Private, owner),
+                ("group", Some(owner)) => instance.set_access(AccessType::Group, owner),
+                ("groupAccessType", Some(value)) => {
+                    instance.group_access_type = Some(value.to_string())
+                }
+                ("region", Some(value)) => instance.region = Some(value.to_string()),
+                ("nonce", Some(value)) => instance.nonce = Some(value.to_string()),
+                ("canRequestInvite", None) => instance.can_request_invite = true,
+                ("strict", None) => instance.strict = true,
+                _ => instance.extra_tags.push(tag.to_string()),
+            }
+        }
+
+        Ok(instance)
+    }
+
+    fn set_access(&mut self, access_type: AccessType, owner: &str) {
+        self.access_type = access_type;
+        self.owner_id = Some(owner.to_string());
+    }
+}
+
+/// Splits `name(value)` into its parts; a bare `name` has no value.
+fn split_tag(tag: &str) -> Option<(&str, Option<&str>)> {
+    match tag.split_once('(') {
+        Some((key, value)) => {
+            let value = value.strip_suffix(')')?;
+            (!key.is_empty()).then_some((key, Some(value)))
+        }
+        None => (!tag.is_empty() && !tag.contains(')')).then_some((tag, None)),
+    }
+}
+
+impl FromStr for Location {
+    type Err = LocationParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err(LocationParseError::Empty),
+            "offline" | "offline:offline" => Ok(Location::Offline),
+            "private" | "private:private" => Ok(Location::Private),
+            "traveling" | "traveling:traveling" => Ok(Location::Traveling),
+            _ => InstanceLocation::parse(s).map(Location::Instance),
+        }
+    }
+}
+
+impl fmt::Display for InstanceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.world_id)?;
+        let Some(name) = &self.instance_name else {
+            return Ok(());
+        };
+        write!(f, ":{}", name)?;
+
+        if self.access_type != AccessType::Public {
+            write!(
+                f,
+                "~{}({})",
+                self.access_type,
+                self.owner_id.as_deref().unwrap_or_default()
+            )?;
+        }
+        if let Some(group_access_type) = &self.group_access_type {
+            write!(f, "~groupAccessType({})", group_access_type)?;
+        }
+        if self.can_request_invite {
+            f.write_str("~canRequestInvite")?;
+        }
+        if let Some(region) = &self.region {
+            write!(f, "~region({})", region)?;
+        }
+        if let Some(nonce) = &self.nonce {
+            write!(f, "~nonce({})", nonce)?;
+        }
+        if self.strict {
+            f.write_str("~strict")?;
+        }
+        for tag in &self.extra_tags {
+            write!(f, "~{}", tag)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Offline => f.write_str("offline"),
+            Location::Private => f.write_str("private"),
+            Location::Traveling => f.write_str("traveling"),
+            Location::Instance(instance) => instance.fmt(f),
+        }
+    }
+}
+
+impl TryFrom<String> for Location {
+    type Error = LocationParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Location> for String {
+    fn from(location: Location) -> Self {
+        location.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_round_trip(location: &str) -> Location {
+        let parsed: Location = location.parse().unwrap();
+        assert_eq!(parsed.to_string(), location);
+        parsed
+    }
+
+    #[test]
+    fn round_trips_special_locations() {
+        assert_eq!(assert_round_trip("offline"), Location::Offline);
+        assert_eq!(assert_round_trip("private"), Location::Private);
+        assert_eq!(assert_round_trip("traveling"), Location::Traveling);
+        assert_eq!("offline:offline".parse(), Ok(Location::Offline));
+        assert_eq!("traveling:traveling".parse(), Ok(Location::Traveling));
+    }
+
+    #[test]
+    fn round_trips_bare_world_id() {
+        let location = assert_round_trip("wrld_4432ea9b-729c-46e3-8eaf-846aa0a37fdd");
+        let instance = location.instance().unwrap();
+        assert_eq!(instance.instance_name, None);
+        assert_eq!(instance.access_type, AccessType::Public);
+    }
+
+    #[test]
+    fn round_trips_public_instance() {
+        let location = assert_round_trip("wrld_x:12345~region(jp)");
+        assert_eq!(location.access_type(), Some(AccessType::Public));
+        assert_eq!(location.region(), Some("jp"));
+    }
+
+    #[test]
+    fn round_trips_access_types() {
+        for (tag, access_type) in [
+            ("hidden", AccessType::Hidden),
+            ("friends", AccessType::Friends),
+            ("private", AccessType::Private),
+            ("group", AccessType::Group),
+        ] {
+            let location = assert_round_trip(&format!(
+                "wrld_x:12345~{}(usr_owner)~region(us)~nonce(abc)",
+                tag
+            ));
+            let instance = location.instance().unwrap();
+            assert_eq!(instance.access_type, access_type);
+            assert_eq!(instance.owner_id.as_deref(), Some("usr_owner"));
+            assert_eq!(instance.nonce.as_deref(), Some("abc"));
+        }
+    }
+
+    #[test]
+    fn round_trips_every_known_tag() {
+        let location = assert_round_trip(
+            "wrld_x:12345~group(grp_y)~groupAccessType(plus)~canRequestInvite~region(eu)~nonce(n0)~strict",
+        );
+        let instance = location.instance().unwrap();
+        assert_eq!(instance.access_type, AccessType::Group);
+        assert_eq!(instance.owner_id.as_deref(), Some("grp_y"));
+        assert_eq!(instance.group_access_type.as_deref(), Some("plus"));
+        assert!(instance.can_request_invite);
+        assert_eq!(instance.region.as_deref(), Some("eu"));
+        assert_eq!(instance.nonce.as_deref(), Some("n0"));
+        assert!(instance.strict);
+        assert!(instance.extra_tags.is_empty());
+    }
+
+    #[test]
+    fn keeps_unknown_tags_after_known_ones() {
+        assert_round_trip("wrld_x:12345~region(jp)~shiny(1)");
+
+        let location: Location = "wrld_x:12345~shiny(1)~region(jp)".parse().unwrap();
+        assert_eq!(location.to_string(), "wrld_x:12345~region(jp)~shiny(1)");
+        assert_eq!(location.to_string().parse::<Location>(), Ok(location));
+    }
+
+    #[test]
+    fn rejects_malformed_locations() {
+        assert_eq!("".parse::<Location>(), Err(LocationParseError::Empty));
+        assert!(matches!(
+            "wrld_x:".parse::<Location>(),
+            Err(LocationParseError::MissingInstanceName(_))
+        ));
+        assert!(matches!(
+            ":12345".parse::<Location>(),
+            Err(LocationParseError::MissingWorldId(_))
+        ));
+        assert!(matches!(
+            "~region(jp".parse::<Location>(),
+            Err(LocationParseError::MissingWorldId(_))
+        ));
+        assert!(matches!(
+            "wrld_x~region(jp)".parse::<Location>(),
+            Err(LocationParseError::MissingInstanceName(_))
+        ));
+        assert!(matches!(
+            "wrld_x:12345~region(jp".parse::<Location>(),
+            Err(LocationParseError::MalformedTag { .. })
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod location;
 pub mod response;
 
 use serde::{Deserialize, Serialize};
@@ -1,30 +1,114 @@
+use crate::account::AccountSession;
+use crate::auth::{self, SessionRefreshError};
 use crate::client;
-use crate::services::event_service;
+use crate::config;
+use crate::event_bus::{DomainEvent, GLOBAL_EVENT_BUS};
+use crate::ingest::{IngestConfig, IngestFrame, IngestQueue};
+use crate::reconnect::{Backoff, ReconnectPolicy};
+use crate::recording::FrameRecorder;
+use crate::services::event_service::{self, EventOutcome, OfflineExpiry};
+use crate::services::pipeline_event_service::{self, ArchivedEvent};
 use anyhow::Result;
 use chrono::{DateTime, Local, Utc};
-use futures_util::StreamExt;
+use futures_util::{SinkExt, StreamExt};
 use reqwest::header::{HeaderValue, USER_AGENT};
 use serde_json::Value;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock, Weak};
 use std::time::Duration;
-use tokio::sync::{mpsc, RwLock};
-use tokio::time::sleep;
+use tokio::net::TcpStream;
+use tokio::sync::{watch, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{interval, sleep, timeout_at, Instant, MissedTickBehavior};
 use tokio_tungstenite::{
     connect_async,
-    tungstenite::{client::IntoClientRequest, Message},
+    tungstenite::{
+        self,
+        client::IntoClientRequest,
+        protocol::{frame::coding::CloseCode, CloseFrame},
+        Message,
+    },
+    MaybeTlsStream, WebSocketStream,
 };
 use url::Url;
 
-pub struct PipelineHandler {}
+#[derive(Debug, thiserror::Error)]
+pub enum PipelineError {
+    #[error("pipeline rejected the auth token: {0}")]
+    AuthRejected(String),
+}
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const DEFAULT_PIPELINE_URL: &str = "wss://pipeline.vrchat.cloud/";
+const DEFAULT_PING_INTERVAL_SECS: u64 = 30;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 90;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 10;
+
+/// Pipeline websocket endpoint, from `PIPELINE_URL`.
+pub fn pipeline_url() -> String {
+    std::env::var("PIPELINE_URL").unwrap_or_else(|_| DEFAULT_PIPELINE_URL.to_string())
+}
+
+struct HeartbeatConfig {
+    ping_interval: Duration,
+    idle_timeout: Duration,
+}
+
+impl HeartbeatConfig {
+    /// Reads `PIPELINE_PING_INTERVAL_SECS` and `PIPELINE_IDLE_TIMEOUT_SECS`.
+    fn from_env() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(config::positive_env_var(
+                "PIPELINE_PING_INTERVAL_SECS",
+                DEFAULT_PING_INTERVAL_SECS,
+            )),
+            idle_timeout: Duration::from_secs(config::positive_env_var(
+                "PIPELINE_IDLE_TIMEOUT_SECS",
+                DEFAULT_IDLE_TIMEOUT_SECS,
+            )),
+        }
+    }
+}
+
+pub struct PipelineHandler {
+    ingest: Arc<IngestQueue>,
+    status: Arc<RwLock<PipelineStatus>>,
+    shutdown: watch::Receiver<bool>,
+    recorder: Option<Arc<FrameRecorder>>,
+    account: Weak<AccountSession>,
+    observer_user_id: Option<String>,
+    connected_at: OnceLock<Instant>,
+}
 
 impl PipelineHandler {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(
+        ingest: Arc<IngestQueue>,
+        status: Arc<RwLock<PipelineStatus>>,
+        shutdown: watch::Receiver<bool>,
+        recorder: Option<Arc<FrameRecorder>>,
+        account: Weak<AccountSession>,
+        observer_user_id: Option<String>,
+    ) -> Self {
+        Self {
+            ingest,
+            status,
+            shutdown,
+            recorder,
+            account,
+            observer_user_id,
+            connected_at: OnceLock::new(),
+        }
+    }
+
+    /// How long the socket has been (or was) open, if it ever connected.
+    pub fn uptime(&self) -> Option<Duration> {
+        self.connected_at.get().map(Instant::elapsed)
     }
 
-    pub async fn listen(&self, auth_token: &str) -> Result<()> {
-        let url_str = format!("wss://pipeline.vrchat.cloud/?authToken={}", auth_token);
-        let _url = Url::parse(&url_str)?;
+    pub async fn listen(&self, base_url: &str, auth_token: &str) -> Result<()> {
+        let mut url = Url::parse(base_url)?;
+        url.query_pairs_mut().append_pair("authToken", auth_token);
+        let url_str = url.to_string();
 
         let mut request = url_str.clone().into_client_request()?;
 
@@ -34,143 +118,484 @@ impl PipelineHandler {
             .insert(USER_AGENT, HeaderValue::from_str(&user_agent_value)?);
         println!("Connect Pipeline...");
         println!("token: {}", url_str.clone());
-        let (ws_stream, response) = connect_async(request).await?;
+        let mut shutdown = self.shutdown.clone();
+        let connected = tokio::select! {
+            connected = connect_async(request) => connected,
+            _ = shutdown_requested(&mut shutdown) => return Ok(()),
+        };
+        let (ws_stream, response) = match connected {
+            Ok(connected) => connected,
+            Err(tungstenite::Error::Http(response))
+                if matches!(response.status().as_u16(), 401 | 403) =>
+            {
+                return Err(PipelineError::AuthRejected(format!(
+                    "HTTP {} on connect",
+                    response.status()
+                ))
+                .into());
+            }
+            Err(e) => return Err(e.into()),
+        };
         println!("Connect Successful HTTP Response: {}", response.status());
         println!("-----------------------------------------");
 
-        let (_write, mut read) = ws_stream.split();
+        GLOBAL_EVENT_BUS.publish(DomainEvent::PipelineConnected {
+            account_user_id: self.observer_user_id.clone(),
+        });
+
+        // Events may have been missed while disconnected.
+        if let Some(account) = self.account.upgrade() {
+            account.request_friend_sync();
+        }
+
+        let _ = self.connected_at.set(Instant::now());
+        {
+            let mut status = self.status.write().await;
+            status.connected = true;
+            status.last_message_time = Some(Utc::now());
+            // The token was accepted, whatever happened to the last refresh.
+            status.auth_state = PipelineAuthState::Authenticated;
+        }
+
+        let result = self.read_frames(ws_stream).await;
+
+        self.status.write().await.connected = false;
+        GLOBAL_EVENT_BUS.publish(DomainEvent::PipelineDisconnected {
+            account_user_id: self.observer_user_id.clone(),
+        });
+
+        result
+    }
+
+    /// Reads frames until the socket closes, pinging the server while it is quiet and
+    /// giving up once nothing at all has arrived within the idle timeout.
+    async fn read_frames(&self, ws_stream: WsStream) -> Result<()> {
+        let (mut write, mut read) = ws_stream.split();
+        let heartbeat = HeartbeatConfig::from_env();
+
+        let mut ping_interval = interval(heartbeat.ping_interval);
+        ping_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick completes immediately; the connection was just established.
+        ping_interval.tick().await;
+
+        let idle = sleep(heartbeat.idle_timeout);
+        tokio::pin!(idle);
 
-        while let Some(msg) = read.next().await {
-            match msg {
-                Ok(message) => {
-                    if let Err(e) = self.handle_message(message).await {
-                        eprintln!("handle_message error: {}", e);
+        let mut shutdown = self.shutdown.clone();
+
+        loop {
+            tokio::select! {
+                _ = shutdown_requested(&mut shutdown) => {
+                    let close = CloseFrame {
+                        code: CloseCode::Normal,
+                        reason: "shutting down".into(),
+                    };
+                    if let Err(e) = write.send(Message::Close(Some(close))).await {
+                        eprintln!("Failed to send close frame: {}", e);
                     }
+                    return Ok(());
                 }
-                Err(e) => {
-                    eprintln!("Connection error: {}", e);
-                    break;
+                msg = read.next() => {
+                    let Some(msg) = msg else {
+                        return Ok(());
+                    };
+
+                    match msg {
+                        Ok(message) => {
+                            // Any traffic, including pongs, proves the connection is alive.
+                            self.status.write().await.last_message_time = Some(Utc::now());
+                            idle.as_mut().reset(Instant::now() + heartbeat.idle_timeout);
+
+                            // Server pings are answered by tungstenite on the next read or write.
+                            if let Err(e) = self.handle_message(message).await {
+                                if e.is::<PipelineError>() {
+                                    return Err(e);
+                                }
+                                eprintln!("handle_message error: {}", e);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Connection error: {}", e);
+                            return Ok(());
+                        }
+                    }
+                }
+                _ = ping_interval.tick() => {
+                    if let Err(e) = write.send(Message::Ping(Default::default())).await {
+                        eprintln!("Failed to send ping: {}", e);
+                        return Ok(());
+                    }
+                }
+                _ = &mut idle => {
+                    return Err(anyhow::anyhow!(
+                        "no pipeline traffic for {:?}, reconnecting",
+                        heartbeat.idle_timeout
+                    ));
                 }
             }
         }
-
-        Ok(())
     }
 
     async fn handle_message(&self, msg: Message) -> Result<()> {
+        if let Message::Close(Some(frame)) = &msg {
+            // Only VRChat's own auth close codes; a generic 1008 policy close is not a
+            // statement about the token.
+            if matches!(
+                frame.code,
+                CloseCode::Library(4001) | CloseCode::Library(4003)
+            ) {
+                return Err(PipelineError::AuthRejected(format!(
+                    "closed with {}: {}",
+                    frame.code, frame.reason
+                ))
+                .into());
+            }
+        }
+
         if let Message::Text(text) = msg {
             println!("[raw]: {}", text);
+            let received_at = Utc::now();
 
-            let outer_json: Value = serde_json::from_str(&text)?;
+            if let Some(recorder) = &self.recorder {
+                recorder.record(&text, received_at);
+            }
 
-            let event_type = outer_json["type"].as_str().unwrap_or("unknown");
-            let content_value = &outer_json["content"];
+            // An invalidated token is reported as `{"err": "..."}` right before the close.
+            if let Some(reason) = Self::rejection_reason(&text) {
+                return Err(PipelineError::AuthRejected(reason).into());
+            }
 
-            let final_content = if content_value.is_string() {
-                let content_str = content_value.as_str().unwrap();
-                serde_json::from_str(content_str)?
-            } else {
-                content_value.clone()
+            let (event_type, final_content) = match Self::decode_frame(&text) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    event_service::record_event_outcome("unknown", EventOutcome::InvalidFrame);
+                    let archived = ArchivedEvent {
+                        event_type: "unknown".to_string(),
+                        content: Some(Value::String(text.to_string())),
+                        received_at,
+                        outcome: EventOutcome::InvalidFrame,
+                        error: Some(e.to_string()),
+                        observer_user_id: self.observer_user_id.clone(),
+                    };
+                    if let Err(db_err) = pipeline_event_service::archive_event(archived).await {
+                        log::error!("Failed to archive invalid frame: {}", db_err);
+                    }
+                    return Err(e);
+                }
             };
 
             println!("\n[event type]: {}", event_type);
             println!("[event content]:\n{:#?}", final_content);
             println!("Received at: {:?}", Local::now().format("%H:%M:%S"));
 
-            if let Err(e) = event_service::process_websocket_event(event_type, &final_content).await
-            {
-                log::error!("Failed to process event {}: {}", event_type, e);
-            } else {
-                log::info!("Event {} processed and saved to database", event_type);
-            }
+            GLOBAL_EVENT_BUS.publish(DomainEvent::PipelineFrame {
+                account_user_id: self.observer_user_id.clone(),
+                event_type: event_type.clone(),
+                content: final_content.clone(),
+                received_at,
+            });
+
+            self.ingest
+                .push(IngestFrame {
+                    event_type,
+                    content: final_content,
+                    received_at,
+                })
+                .await;
         }
         Ok(())
     }
+
+    fn rejection_reason(text: &str) -> Option<String> {
+        let outer_json: Value = serde_json::from_str(text).ok()?;
+        let reason = outer_json.get("err")?.as_str()?;
+        reason.contains("authToken").then(|| reason.to_string())
+    }
+
+    /// Splits a frame into its type and content, decoding `content` when it is a JSON string.
+    pub(crate) fn decode_frame(text: &str) -> Result<(String, Value)> {
+        let outer_json: Value = serde_json::from_str(text)?;
+
+        let event_type = outer_json["type"].as_str().unwrap_or("unknown").to_string();
+        let content_value = &outer_json["content"];
+
+        // Most events double-encode `content`, but e.g. see-notification sends a bare id.
+        let final_content = if content_value.is_string() {
+            let content_str = content_value.as_str().unwrap();
+            serde_json::from_str(content_str).unwrap_or_else(|_| content_value.clone())
+        } else {
+            content_value.clone()
+        };
+
+        Ok((event_type, final_content))
+    }
+}
+
+/// Waits until the manager asks to stop; a dropped sender counts as a request.
+async fn shutdown_requested(shutdown: &mut watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+/// Sleeps for `delay`, returning `false` early if shutdown is requested meanwhile.
+async fn sleep_unless_shutdown(delay: Duration, shutdown: &mut watch::Receiver<bool>) -> bool {
+    tokio::select! {
+        _ = sleep(delay) => true,
+        _ = shutdown_requested(shutdown) => false,
+    }
+}
+
+/// Drains the ingest queue, running each frame through `event_service` and archiving
+/// every batch in one insert, until the queue is closed.
+async fn run_persistence_worker(ingest: Arc<IngestQueue>, observer_user_id: Option<String>) {
+    loop {
+        let batch = ingest.pop_batch().await;
+        if batch.is_empty() {
+            // Only returned once the queue is closed and drained.
+            break;
+        }
+
+        let mut archived = Vec::with_capacity(batch.len());
+        for frame in batch {
+            let (outcome, error) = match event_service::process_websocket_event(
+                observer_user_id.as_deref(),
+                &frame.event_type,
+                &frame.content,
+                frame.received_at,
+                OfflineExpiry::Timer,
+            )
+            .await
+            {
+                Ok(outcome) => {
+                    log::info!("Event {} processed and saved to database", frame.event_type);
+                    (outcome, None)
+                }
+                Err(e) => {
+                    log::error!("Failed to process event {}: {}", frame.event_type, e);
+                    (EventOutcome::for_error(&e), Some(e.to_string()))
+                }
+            };
+
+            archived.push(ArchivedEvent {
+                event_type: frame.event_type,
+                content: Some(frame.content),
+                received_at: frame.received_at,
+                outcome,
+                error,
+                observer_user_id: observer_user_id.clone(),
+            });
+        }
+
+        let count = archived.len();
+        let result = pipeline_event_service::archive_events(archived).await;
+        if let Err(e) = &result {
+            log::error!("Failed to archive {} pipeline event(s): {}", count, e);
+        }
+        ingest.finish_batch(result.is_ok());
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineAuthState {
+    Authenticated,
+    /// The token was rejected and a new one is being requested.
+    Refreshing,
+    /// No new token could be had after a rejection; the rejected one is retried on the
+    /// next reconnect.
+    RefreshFailed,
+    /// The session expired and only a new login with credentials can restore it.
+    NeedsRelogin,
+    /// The session expired and logging in again requires a two-factor code.
+    NeedsTwoFactor,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct PipelineStatus {
     pub connected: bool,
     pub last_message_time: Option<DateTime<Utc>>,
     pub reconnect_count: u32,
+    /// When the next connection attempt is scheduled, while waiting to reconnect.
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    pub auth_state: PipelineAuthState,
 }
 
 pub struct PipelineManager {
+    url: String,
     auth_token: String,
+    account: Weak<AccountSession>,
+    observer_user_id: Option<String>,
     status: Arc<RwLock<PipelineStatus>>,
-    shutdown_sender: Option<mpsc::UnboundedSender<()>>,
+    shutdown_sender: Option<watch::Sender<bool>>,
+    ingest: Option<Arc<IngestQueue>>,
+    connection_task: Option<JoinHandle<()>>,
+    persistence_task: Option<JoinHandle<()>>,
 }
 
 impl PipelineManager {
     pub fn new(auth_token: String) -> Self {
         Self {
+            url: pipeline_url(),
             auth_token,
+            account: Weak::new(),
+            observer_user_id: None,
             status: Arc::new(RwLock::new(PipelineStatus {
                 connected: false,
                 last_message_time: None,
                 reconnect_count: 0,
+                next_attempt_at: None,
+                auth_state: PipelineAuthState::Authenticated,
             })),
             shutdown_sender: None,
+            ingest: None,
+            connection_task: None,
+            persistence_task: None,
         }
     }
 
+    /// Connects to `url` instead of the configured pipeline endpoint, e.g. a mock server.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Runs the pipeline for `account`: frames are tagged with its user id, and rejected
+    /// tokens are refreshed through its session. Without an account a rejected token
+    /// stops the pipeline.
+    pub fn with_account(mut self, account: &Arc<AccountSession>) -> Self {
+        self.account = Arc::downgrade(account);
+        self
+    }
+
     pub async fn start(&mut self) {
-        let (shutdown_tx, mut shutdown_rx) = mpsc::unbounded_channel();
+        let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
         self.shutdown_sender = Some(shutdown_tx);
 
-        let auth_token = self.auth_token.clone();
+        // Persistence reads from its own bounded queue rather than the event bus, which
+        // would silently skip frames whenever the database falls behind.
+        let account = self.account.clone();
+        let observer_user_id = account.upgrade().and_then(|account| account.user_id());
+        self.observer_user_id = observer_user_id.clone();
+        let ingest = Arc::new(IngestQueue::new(
+            IngestConfig::from_env().for_account(observer_user_id.as_deref()),
+        ));
+        let recorder = FrameRecorder::from_env(observer_user_id.as_deref()).map(Arc::new);
+        self.persistence_task = Some(tokio::spawn(run_persistence_worker(
+            ingest.clone(),
+            observer_user_id.clone(),
+        )));
+        self.ingest = Some(ingest.clone());
+
+        let url = self.url.clone();
+        let mut auth_token = self.auth_token.clone();
         let status = self.status.clone();
 
-        tokio::spawn(async move {
-            let mut reconnect_count = 0;
-            let max_retries = 50;
+        self.connection_task = Some(tokio::spawn(async move {
+            let mut backoff = Backoff::new(ReconnectPolicy::pipeline());
 
             loop {
-                if shutdown_rx.try_recv().is_ok() {
+                if *shutdown_rx.borrow() {
                     println!("Pipeline manager received shutdown signal");
                     break;
                 }
 
                 println!(
                     "Starting pipeline connection attempt {}...",
-                    reconnect_count + 1
+                    backoff.attempt() + 1
                 );
 
                 {
                     let mut status_guard = status.write().await;
                     status_guard.connected = false;
-                    status_guard.reconnect_count = reconnect_count;
+                    status_guard.reconnect_count = backoff.attempt();
+                    status_guard.next_attempt_at = None;
                 }
 
-                let handler = PipelineHandler::new();
-                match handler.listen(&auth_token).await {
+                let handler = PipelineHandler::new(
+                    ingest.clone(),
+                    status.clone(),
+                    shutdown_rx.clone(),
+                    recorder.clone(),
+                    account.clone(),
+                    observer_user_id.clone(),
+                );
+                let result = handler.listen(&url, &auth_token).await;
+                backoff.connection_ended(handler.uptime());
+
+                match result {
                     Ok(_) => {
                         println!("Pipeline connection ended normally");
-                        reconnect_count = 0;
-                        println!("Reconnecting pipeline in 5 seconds...");
-                        sleep(Duration::from_secs(5)).await;
+                    }
+                    Err(e) if e.is::<PipelineError>() => {
+                        eprintln!("Pipeline auth failed: {}", e);
+                        status.write().await.auth_state = PipelineAuthState::Refreshing;
+
+                        let refreshed = match account.upgrade() {
+                            Some(account) => auth::refresh_pipeline_token(&account).await,
+                            None => Err(SessionRefreshError::NeedsRelogin),
+                        };
+                        // Reconnecting still goes through the backoff below, so a token that
+                        // keeps getting rejected cannot turn into a tight reconnect loop.
+                        match refreshed {
+                            Ok(token) if token == auth_token => {
+                                eprintln!("Pipeline token refresh returned the rejected token");
+                                status.write().await.auth_state = PipelineAuthState::RefreshFailed;
+                            }
+                            Ok(token) => {
+                                println!("Pipeline token refreshed");
+                                auth_token = token;
+                                status.write().await.auth_state = PipelineAuthState::Authenticated;
+                            }
+                            Err(SessionRefreshError::Transient(reason)) => {
+                                eprintln!("Pipeline token refresh failed: {}", reason);
+                                status.write().await.auth_state = PipelineAuthState::RefreshFailed;
+                            }
+                            Err(refresh_error) => {
+                                let needs_two_factor =
+                                    matches!(refresh_error, SessionRefreshError::NeedsTwoFactor);
+                                log::error!("Pipeline stopped: {}", refresh_error);
+                                status.write().await.auth_state = if needs_two_factor {
+                                    PipelineAuthState::NeedsTwoFactor
+                                } else {
+                                    PipelineAuthState::NeedsRelogin
+                                };
+                                GLOBAL_EVENT_BUS.publish(DomainEvent::PipelineAuthRequired {
+                                    account_user_id: observer_user_id.clone(),
+                                    needs_two_factor,
+                                });
+
+                                // A new login replaces this manager, so just wait to be shut down.
+                                shutdown_requested(&mut shutdown_rx).await;
+                                println!("Pipeline manager received shutdown signal");
+                                break;
+                            }
+                        }
                     }
                     Err(e) => {
-                        reconnect_count += 1;
                         eprintln!(
-                            "Pipeline connection failed (attempt {}/{}): {}",
-                            reconnect_count, max_retries, e
+                            "Pipeline connection failed (attempt {}): {}",
+                            backoff.attempt() + 1,
+                            e
                         );
+                    }
+                }
 
-                        if reconnect_count >= max_retries {
-                            eprintln!("Pipeline max retries exceeded, but this is a service, so we'll keep trying...");
-                            reconnect_count = 0;
-                        }
+                if *shutdown_rx.borrow() {
+                    continue;
+                }
 
-                        let delay = Duration::from_secs(2u64.pow(reconnect_count.min(5)));
-                        println!("Reconnecting pipeline in {:?}...", delay);
-                        sleep(delay).await;
-                    }
+                let delay = backoff.next_delay();
+                {
+                    let mut status_guard = status.write().await;
+                    status_guard.reconnect_count = backoff.attempt();
+                    status_guard.next_attempt_at = chrono::Duration::from_std(delay)
+                        .ok()
+                        .map(|delay| Utc::now() + delay);
                 }
+                println!("Reconnecting pipeline in {:?}...", delay);
+                sleep_unless_shutdown(delay, &mut shutdown_rx).await;
             }
 
             println!("Pipeline manager shut down");
-        });
+        }));
 
         println!("Pipeline manager started in background");
     }
@@ -179,9 +604,50 @@ impl PipelineManager {
         self.status.read().await.clone()
     }
 
+    /// Closes the socket, persists everything already queued, commits this account's pending
+    /// offlines and waits for both tasks to finish, aborting whatever is still running once the shutdown timeout passes.
     pub async fn shutdown(&mut self) {
-        if let Some(sender) = &self.shutdown_sender {
-            let _ = sender.send(());
+        let deadline = Instant::now() + shutdown_timeout();
+
+        if let Some(sender) = self.shutdown_sender.take() {
+            let _ = sender.send(true);
+        }
+
+        if let Some(task) = self.connection_task.take() {
+            await_task("pipeline connection", task, deadline).await;
+        }
+
+        // Nothing else can be queued once the connection task has stopped.
+        if let Some(ingest) = self.ingest.take() {
+            ingest.close();
         }
+
+        if let Some(task) = self.persistence_task.take() {
+            await_task("pipeline persistence", task, deadline).await;
+
+            // Nobody is left to see these friends come back, so their offlines stand.
+            let observer_user_id = self.observer_user_id.as_deref();
+            let flushed = event_service::flush_pending_offline(observer_user_id);
+            if timeout_at(deadline, flushed).await.is_err() {
+                log::warn!("Timed out committing pending offlines");
+            }
+        }
+
+        println!("Pipeline manager stopped");
+    }
+}
+
+/// How long shutdown waits for the pipeline tasks, from `PIPELINE_SHUTDOWN_TIMEOUT_SECS`.
+fn shutdown_timeout() -> Duration {
+    Duration::from_secs(config::positive_env_var(
+        "PIPELINE_SHUTDOWN_TIMEOUT_SECS",
+        DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+    ))
+}
+
+async fn await_task(name: &str, mut task: JoinHandle<()>, deadline: Instant) {
+    if timeout_at(deadline, &mut task).await.is_err() {
+        log::warn!("Timed out waiting for {} task, aborting it", name);
+        task.abort();
     }
 }
@@ -0,0 +1,219 @@
+use crate::config;
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::time::sleep;
+
+/// Exponential backoff settings shared by the pipeline reconnect loop and REST retries.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Delay before the first retry.
+    pub initial: Duration,
+    /// Upper bound for any single delay.
+    pub max: Duration,
+    /// Growth factor applied per consecutive failure.
+    pub multiplier: f64,
+    /// Picks a random delay between zero and the computed one ("full jitter"), so many
+    /// clients dropped at once do not reconnect in lockstep.
+    pub jitter: bool,
+    /// A connection that stayed up at least this long resets the attempt counter.
+    pub reset_after: Duration,
+}
+
+impl ReconnectPolicy {
+    /// Defaults for the pipeline websocket, overridable with `PIPELINE_RECONNECT_*`.
+    pub fn pipeline() -> Self {
+        Self::from_env(
+            "PIPELINE_RECONNECT",
+            Self {
+                initial: Duration::from_secs(1),
+                max: Duration::from_secs(60),
+                multiplier: 2.0,
+                jitter: true,
+                reset_after: Duration::from_secs(60),
+            },
+        )
+    }
+
+    /// Defaults for VRChat REST calls, overridable with `API_RETRY_*`.
+    pub fn rest() -> Self {
+        Self::from_env(
+            "API_RETRY",
+            Self {
+                initial: Duration::from_millis(500),
+                max: Duration::from_secs(30),
+                multiplier: 2.0,
+                jitter: true,
+                reset_after: Duration::ZERO,
+            },
+        )
+    }
+
+    /// Reads `{prefix}_INITIAL_MS`, `{prefix}_MAX_MS`, `{prefix}_MULTIPLIER`,
+    /// `{prefix}_JITTER` and `{prefix}_RESET_AFTER_SECS`, keeping `defaults` for anything
+    /// unset or invalid.
+    pub fn from_env(prefix: &str, defaults: Self) -> Self {
+        fn var<T: FromStr>(prefix: &str, name: &str) -> Option<T> {
+            config::env_var(&format!("{}_{}", prefix, name))
+        }
+
+        let initial = var(prefix, "INITIAL_MS")
+            .map(Duration::from_millis)
+            .unwrap_or(defaults.initial);
+        let max = var(prefix, "MAX_MS")
+            .map(Duration::from_millis)
+            .unwrap_or(defaults.max)
+            .max(initial);
+        let multiplier = var(prefix, "MULTIPLIER")
+            .filter(|multiplier: &f64| *multiplier >= 1.0)
+            .unwrap_or(defaults.multiplier);
+        let jitter = var(prefix, "JITTER").unwrap_or(defaults.jitter);
+        let reset_after = var(prefix, "RESET_AFTER_SECS")
+            .map(Duration::from_secs)
+            .unwrap_or(defaults.reset_after);
+
+        Self {
+            initial,
+            max,
+            multiplier,
+            jitter,
+            reset_after,
+        }
+    }
+
+    /// Delay before retry number `attempt`, counting from zero.
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exponent = attempt.min(i32::MAX as u32) as i32;
+        let secs = self.initial.as_secs_f64() * self.multiplier.powi(exponent);
+        let capped = Duration::from_secs_f64(secs.min(self.max.as_secs_f64()));
+
+        if self.jitter {
+            capped.mul_f64(rand::random::<f64>())
+        } else {
+            capped
+        }
+    }
+}
+
+/// Consecutive-failure counter driven by a `ReconnectPolicy`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self { policy, attempt: 0 }
+    }
+
+    /// Failures since the last reset.
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Returns the delay before the next retry and counts the failure.
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.policy.delay_for(self.attempt);
+        self.attempt = self.attempt.saturating_add(1);
+        delay
+    }
+
+    /// Records how long the last connection stayed up, resetting once it was stable.
+    pub fn connection_ended(&mut self, uptime: Option<Duration>) {
+        if uptime.is_some_and(|uptime| uptime >= self.policy.reset_after) {
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+/// Runs `operation` until it succeeds, fails with an error `is_transient` rejects, or
+/// `max_attempts` attempts have been made, sleeping per `policy` in between.
+pub async fn retry<T, E, F, Fut>(
+    policy: &ReconnectPolicy,
+    max_attempts: u32,
+    is_transient: impl Fn(&E) -> bool,
+    mut operation: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = Backoff::new(policy.clone());
+    loop {
+        match operation().await {
+            Ok(value) => return Ok(value),
+            Err(e) if is_transient(&e) && backoff.attempt() + 1 < max_attempts => {
+                let delay = backoff.next_delay();
+                log::warn!(
+                    "Transient failure (attempt {}/{}), retrying in {:?}",
+                    backoff.attempt(),
+                    max_attempts,
+                    delay
+                );
+                sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(jitter: bool) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(2),
+            multiplier: 2.0,
+            jitter,
+            reset_after: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_the_cap() {
+        let policy = policy(false);
+        let delays: Vec<u64> = (0..7)
+            .map(|attempt| policy.delay_for(attempt).as_millis() as u64)
+            .collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1600, 2000, 2000]);
+        assert_eq!(policy.delay_for(u32::MAX), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn full_jitter_stays_within_the_capped_delay() {
+        let policy = policy(true);
+        for attempt in 0..8 {
+            // min(cap, initial * 2^attempt)
+            let bound = Duration::from_millis(100 << attempt).min(Duration::from_secs(2));
+            for _ in 0..100 {
+                assert!(policy.delay_for(attempt) <= bound, "attempt {}", attempt);
+            }
+        }
+    }
+
+    #[test]
+    fn reset_starts_over_from_the_first_attempt() {
+        let mut backoff = Backoff::new(policy(false));
+        for _ in 0..4 {
+            backoff.next_delay();
+        }
+        assert_eq!(backoff.attempt(), 4);
+
+        backoff.reset();
+        assert_eq!(backoff.attempt(), 0);
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+
+        // A short-lived connection does not count as stable.
+        backoff.connection_ended(Some(Duration::from_secs(1)));
+        assert_eq!(backoff.attempt(), 1);
+        backoff.connection_ended(Some(Duration::from_secs(60)));
+        assert_eq!(backoff.attempt(), 0);
+    }
+}
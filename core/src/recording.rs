@@ -0,0 +1,199 @@
+use crate::config;
+use crate::pipeline::PipelineHandler;
+use crate::services::event_service::{self, EventOutcome, OfflineExpiry};
+use crate::services::pipeline_event_service::{self, ArchivedEvent};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tokio::time::sleep;
+
+/// One raw pipeline frame as received, a line of a recording file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub received_at: DateTime<Utc>,
+    pub raw: String,
+}
+
+/// Appends every received pipeline frame to a JSONL file.
+pub struct FrameRecorder {
+    path: PathBuf,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl FrameRecorder {
+    pub fn open(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Opens the recording at `PIPELINE_RECORD_PATH`, if set and not empty, with the file
+    /// name suffixed by `account_user_id` so each account records to its own file.
+    pub fn from_env(account_user_id: Option<&str>) -> Option<Self> {
+        let path = std::env::var("PIPELINE_RECORD_PATH")
+            .ok()
+            .filter(|path| !path.is_empty())?;
+        let path = match account_user_id {
+            Some(account_user_id) => config::account_path(Path::new(&path), account_user_id),
+            None => PathBuf::from(path),
+        };
+        match Self::open(&path) {
+            Ok(recorder) => {
+                log::info!("Recording pipeline frames to {}", path.display());
+                Some(recorder)
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to open pipeline recording {}: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    pub fn record(&self, raw: &str, received_at: DateTime<Utc>) {
+        let frame = RecordedFrame {
+            received_at,
+            raw: raw.to_string(),
+        };
+
+        let mut writer = self.writer.lock().unwrap();
+        // Flushed per frame so a crash still leaves everything up to it on disk.
+        let result = serde_json::to_writer(&mut *writer, &frame)
+            .map_err(std::io::Error::from)
+            .and_then(|_| writeln!(writer))
+            .and_then(|_| writer.flush());
+        if let Err(e) = result {
+            log::error!(
+                "Failed to record pipeline frame to {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayTiming {
+    /// Feeds frames back to back.
+    AsFastAsPossible,
+    /// Waits between frames as long as the gaps in the recording.
+    Original,
+}
+
+#[derive(Debug, Default)]
+pub struct ReplaySummary {
+    pub frames: usize,
+    /// Lines that were not a `RecordedFrame` at all.
+    pub unreadable_lines: usize,
+    /// Frames per `EventOutcome::as_str`.
+    pub outcomes: BTreeMap<&'static str, usize>,
+}
+
+/// Feeds a recording through `event_service::process_websocket_event` into the current
+/// database, archiving each frame like the live pipeline does, as observed by
+/// `observer_user_id`.
+///
+/// Time follows the recorded `received_at` in both timings: a pending offline is committed
+/// before the first frame past its grace window, and whatever is still pending at the end
+/// of the file is committed then.
+pub async fn replay_file(
+    path: &Path,
+    timing: ReplayTiming,
+    observer_user_id: Option<&str>,
+) -> anyhow::Result<ReplaySummary> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut summary = ReplaySummary::default();
+    let mut previous_received_at: Option<DateTime<Utc>> = None;
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let frame: RecordedFrame = match serde_json::from_str(&line) {
+            Ok(frame) => frame,
+            Err(e) => {
+                log::warn!("Skipping line {} of {}: {}", index + 1, path.display(), e);
+                summary.unreadable_lines += 1;
+                continue;
+            }
+        };
+
+        if timing == ReplayTiming::Original {
+            if let Some(gap) = previous_received_at
+                .and_then(|previous| (frame.received_at - previous).to_std().ok())
+            {
+                sleep(gap).await;
+            }
+        }
+        previous_received_at = Some(frame.received_at);
+
+        event_service::commit_expired_offline(observer_user_id, frame.received_at).await;
+
+        let archived = replay_frame(observer_user_id, frame).await;
+        summary.frames += 1;
+        *summary
+            .outcomes
+            .entry(archived.outcome.as_str())
+            .or_default() += 1;
+
+        if let Err(e) = pipeline_event_service::archive_event(archived).await {
+            log::error!("Failed to archive replayed frame: {}", e);
+        }
+    }
+
+    event_service::flush_pending_offline(observer_user_id).await;
+
+    Ok(summary)
+}
+
+async fn replay_frame(observer_user_id: Option<&str>, frame: RecordedFrame) -> ArchivedEvent {
+    let (event_type, content) = match PipelineHandler::decode_frame(&frame.raw) {
+        Ok(decoded) => decoded,
+        Err(e) => {
+            event_service::record_event_outcome("unknown", EventOutcome::InvalidFrame);
+            return ArchivedEvent {
+                event_type: "unknown".to_string(),
+                content: Some(Value::String(frame.raw)),
+                received_at: frame.received_at,
+                outcome: EventOutcome::InvalidFrame,
+                error: Some(e.to_string()),
+                observer_user_id: observer_user_id.map(str::to_string),
+            };
+        }
+    };
+
+    let (outcome, error) = match event_service::process_websocket_event(
+        observer_user_id,
+        &event_type,
+        &content,
+        frame.received_at,
+        OfflineExpiry::Manual,
+    )
+    .await
+    {
+        Ok(outcome) => (outcome, None),
+        Err(e) => (EventOutcome::for_error(&e), Some(e.to_string())),
+    };
+
+    ArchivedEvent {
+        event_type,
+        content: Some(content),
+        received_at: frame.received_at,
+        outcome,
+        error,
+        observer_user_id: observer_user_id.map(str::to_string),
+    }
+}
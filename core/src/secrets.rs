@@ -0,0 +1,74 @@
+use magic_crypt::{new_magic_crypt, MagicCryptTrait};
+
+/// Prefix marking a value produced by `encrypt`.
+pub const ENCRYPTED_PREFIX: &str = "encrypted:";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SecretError {
+    #[error("no encryption key, set BOTAN_ENCRYPTION_KEY or BOTAN_ENCRYPTION_KEY_FILE")]
+    MissingKey,
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("failed to decrypt secret: {0}")]
+    Decrypt(String),
+}
+
+/// The key protecting stored secrets, from `BOTAN_ENCRYPTION_KEY` or the file named by
+/// `BOTAN_ENCRYPTION_KEY_FILE`.
+pub fn encryption_key() -> Result<String, SecretError> {
+    if let Some(key) = std::env::var("BOTAN_ENCRYPTION_KEY")
+        .ok()
+        .filter(|key| !key.is_empty())
+    {
+        return Ok(key);
+    }
+
+    let path = std::env::var("BOTAN_ENCRYPTION_KEY_FILE").map_err(|_| SecretError::MissingKey)?;
+    let key = read_trimmed(&path)?;
+    if key.is_empty() {
+        return Err(SecretError::MissingKey);
+    }
+    Ok(key)
+}
+
+/// Encrypts `plaintext` with `key`, returning it with `ENCRYPTED_PREFIX`.
+pub fn encrypt(key: &str, plaintext: &str) -> String {
+    let mc = new_magic_crypt!(key, 256);
+    format!(
+        "{}{}",
+        ENCRYPTED_PREFIX,
+        mc.encrypt_str_to_base64(plaintext)
+    )
+}
+
+/// Decrypts a value produced by `encrypt`; values without the prefix are returned as is.
+pub fn decrypt(key: &str, value: &str) -> Result<String, SecretError> {
+    let Some(encrypted) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(value.to_string());
+    };
+    let mc = new_magic_crypt!(key, 256);
+    mc.decrypt_base64_to_string(encrypted)
+        .map_err(|e| SecretError::Decrypt(e.to_string()))
+}
+
+/// Reads a secret from `path`, decrypting it with `encryption_key` when it was encrypted.
+pub fn read_secret_file(path: &str) -> Result<String, SecretError> {
+    let value = read_trimmed(path)?;
+    if value.starts_with(ENCRYPTED_PREFIX) {
+        decrypt(&encryption_key()?, &value)
+    } else {
+        Ok(value)
+    }
+}
+
+fn read_trimmed(path: &str) -> Result<String, SecretError> {
+    std::fs::read_to_string(path)
+        .map(|value| value.trim().to_string())
+        .map_err(|source| SecretError::Read {
+            path: path.to_string(),
+            source,
+        })
+}
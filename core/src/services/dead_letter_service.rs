@@ -0,0 +1,25 @@
+use crate::database::get_db_connection;
+use crate::entities::event_dead_letters;
+use crate::services::event_service::EventDecodeError;
+use sea_orm::*;
+
+/// Stores an event that could not be decoded so it can be inspected and replayed later.
+pub async fn record_dead_letter(
+    observer_user_id: Option<&str>,
+    error: &EventDecodeError,
+) -> Result<event_dead_letters::Model, DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    event_dead_letters::ActiveModel {
+        event_type: Set(error.event_type.clone()),
+        error_path: Set(error.path.clone()),
+        error_message: Set(error.message.clone()),
+        payload: Set(error.payload.clone()),
+        observer_user_id: Set(observer_user_id.map(str::to_string)),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+}
@@ -1,109 +1,608 @@
+use crate::config;
 use crate::conversions::*;
-use crate::services::user_service;
+use crate::event_bus::{DomainEvent, PresenceState, GLOBAL_EVENT_BUS};
+use crate::models::location::Location;
+use crate::services::{
+    dead_letter_service, friendship_service, location_service, session_service, user_service,
+};
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use vrchatapi::models::UserState;
 
-pub async fn process_websocket_event(event_type: &str, content: &Value) -> Result<()> {
+const DEFAULT_PENDING_OFFLINE_GRACE_SECS: u64 = 170;
+
+struct PendingOffline {
+    since: DateTime<Utc>,
+    /// The grace timer, when `OfflineExpiry::Timer` started one.
+    abort_handle: Option<AbortHandle>,
+}
+
+impl PendingOffline {
+    fn cancel_timer(&self) {
+        if let Some(abort_handle) = &self.abort_handle {
+            abort_handle.abort();
+        }
+    }
+}
+
+/// How pending offlines leave the grace window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OfflineExpiry {
+    /// A timer commits each one once its grace period has passed, for live frames.
+    Timer,
+    /// The caller commits them with `commit_expired_offline`, e.g. a replay that follows
+    /// the recorded timestamps instead of the wall clock.
+    Manual,
+}
+
+/// Pending offlines of one observing account, keyed by user id.
+type PendingOfflineByUser = HashMap<String, PendingOffline>;
+
+/// Offline transitions waiting out the grace window, per observing account. Each account
+/// sees its friends go offline independently.
+static PENDING_OFFLINE: LazyLock<Mutex<HashMap<Option<String>, PendingOfflineByUser>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// How long a friend-offline is held before it is committed, from `PENDING_OFFLINE_GRACE_SECS`.
+///
+/// VRChat often sends friend-offline right before friend-online when someone switches
+/// worlds; a zero grace period commits every offline immediately.
+pub fn pending_offline_grace() -> Duration {
+    let secs =
+        config::env_var("PENDING_OFFLINE_GRACE_SECS").unwrap_or(DEFAULT_PENDING_OFFLINE_GRACE_SECS);
+    Duration::from_secs(secs)
+}
+
+/// Friends of `observer_user_id` whose offline transition is still inside the grace window,
+/// with the time it arrived.
+pub fn pending_offline_users(observer_user_id: Option<&str>) -> HashMap<String, DateTime<Utc>> {
+    PENDING_OFFLINE
+        .lock()
+        .unwrap()
+        .get(&observer_user_id.map(str::to_string))
+        .map(|pending| {
+            pending
+                .iter()
+                .map(|(user_id, pending)| (user_id.clone(), pending.since))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// What happened to a pipeline frame, as archived in `pipeline_events.outcome`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventOutcome {
+    Processed,
+    Unhandled,
+    Failed,
+    DecodeFailed,
+    InvalidFrame,
+}
+
+impl EventOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventOutcome::Processed => "processed",
+            EventOutcome::Unhandled => "unhandled",
+            EventOutcome::Failed => "failed",
+            EventOutcome::DecodeFailed => "decode_failed",
+            EventOutcome::InvalidFrame => "invalid_frame",
+        }
+    }
+
+    pub fn for_error(error: &anyhow::Error) -> Self {
+        if error.is::<EventDecodeError>() {
+            EventOutcome::DecodeFailed
+        } else {
+            EventOutcome::Failed
+        }
+    }
+}
+
+/// A known pipeline event whose content no longer matches our types.
+#[derive(Debug, thiserror::Error)]
+#[error("failed to decode {event_type} event at {path}: {message}")]
+pub struct EventDecodeError {
+    pub event_type: String,
+    pub path: String,
+    pub message: String,
+    pub payload: Value,
+}
+
+/// Per event type outcome counters since the process started.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct EventCounts {
+    pub processed: u64,
+    pub unhandled: u64,
+    pub failed: u64,
+    pub decode_failed: u64,
+    pub invalid_frame: u64,
+}
+
+static EVENT_COUNTS: LazyLock<Mutex<HashMap<String, EventCounts>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Counts `outcome` for `event_type`, returning the updated counters.
+pub fn record_event_outcome(event_type: &str, outcome: EventOutcome) -> EventCounts {
+    let mut counts = EVENT_COUNTS.lock().unwrap();
+    let entry = counts.entry(event_type.to_string()).or_default();
+    match outcome {
+        EventOutcome::Processed => entry.processed += 1,
+        EventOutcome::Unhandled => entry.unhandled += 1,
+        EventOutcome::Failed => entry.failed += 1,
+        EventOutcome::DecodeFailed => entry.decode_failed += 1,
+        EventOutcome::InvalidFrame => entry.invalid_frame += 1,
+    }
+    entry.clone()
+}
+
+pub fn event_counts() -> HashMap<String, EventCounts> {
+    EVENT_COUNTS.lock().unwrap().clone()
+}
+
+/// Processes a frame received through the pipeline of `observer_user_id` at `received_at`,
+/// tagging whatever it persists with that account.
+///
+/// Sessions, locations and offlines are timestamped with `received_at` rather than the time
+/// the frame is processed, which can be much later when the ingest queue is backed up.
+pub async fn process_websocket_event(
+    observer_user_id: Option<&str>,
+    event_type: &str,
+    content: &Value,
+    received_at: DateTime<Utc>,
+    offline_expiry: OfflineExpiry,
+) -> Result<EventOutcome> {
+    let result = dispatch_websocket_event(
+        observer_user_id,
+        event_type,
+        content,
+        received_at,
+        offline_expiry,
+    )
+    .await;
+
+    let outcome = match &result {
+        Ok(outcome) => *outcome,
+        Err(e) => EventOutcome::for_error(e),
+    };
+    let counts = record_event_outcome(event_type, outcome);
+    if outcome == EventOutcome::DecodeFailed {
+        log::error!(
+            "{} event failed to decode ({} failure(s) so far), the VRChat schema may have changed",
+            event_type,
+            counts.decode_failed
+        );
+    }
+
+    result
+}
+
+async fn dispatch_websocket_event(
+    observer_user_id: Option<&str>,
+    event_type: &str,
+    content: &Value,
+    received_at: DateTime<Utc>,
+    offline_expiry: OfflineExpiry,
+) -> Result<EventOutcome> {
     log::info!("Processing event: {}", event_type);
 
-    match event_type {
-        "friend-add" => {
-            if let Ok(event) = serde_json::from_value::<FriendAddEvent>(content.clone()) {
-                process_friend_add_event(event).await?;
-            }
+    let event = match WebsocketEvent::from_parts(event_type, content) {
+        Ok(Some(event)) => event,
+        Ok(None) => {
+            log::warn!("Unknown event type: {}", event_type);
+            return Ok(EventOutcome::Unhandled);
         }
-        "friend-delete" => {
-            if let Ok(event) = serde_json::from_value::<FriendDeleteEvent>(content.clone()) {
-                process_friend_delete_event(event).await?;
+        Err(e) => {
+            let decode_error = EventDecodeError {
+                event_type: event_type.to_string(),
+                path: e.path().to_string(),
+                message: e.inner().to_string(),
+                payload: content.clone(),
+            };
+            log::error!("{}", decode_error);
+
+            if let Err(db_err) =
+                dead_letter_service::record_dead_letter(observer_user_id, &decode_error).await
+            {
+                log::error!("Failed to record dead letter: {}", db_err);
             }
+
+            return Err(decode_error.into());
         }
-        "friend-online" => {
-            if let Ok(event) = serde_json::from_value::<FriendOnlineEvent>(content.clone()) {
-                process_friend_online_event(event).await?;
-            }
+    };
+
+    match event {
+        WebsocketEvent::FriendAdd(event) => {
+            process_friend_add_event(observer_user_id, event).await?
         }
-        "friend-active" => {
-            if let Ok(event) = serde_json::from_value::<FriendActiveEvent>(content.clone()) {
-                process_friend_active_event(event).await?;
-            }
+        WebsocketEvent::FriendDelete(event) => {
+            process_friend_delete_event(observer_user_id, event).await?
         }
-        "friend-offline" => {
-            if let Ok(event) = serde_json::from_value::<FriendOfflineEvent>(content.clone()) {
-                process_friend_offline_event(event).await?;
-            }
+        WebsocketEvent::FriendOnline(event) => {
+            process_friend_online_event(observer_user_id, event, received_at).await?
         }
-        "friend-update" => {
-            if let Ok(event) = serde_json::from_value::<FriendUpdateEvent>(content.clone()) {
-                process_friend_update_event(event).await?;
-            }
+        WebsocketEvent::FriendActive(event) => {
+            process_friend_active_event(observer_user_id, event, received_at).await?
         }
-        "friend-location" => {
-            if let Ok(event) = serde_json::from_value::<FriendLocationEvent>(content.clone()) {
-                process_friend_location_event(event).await?;
-            }
+        WebsocketEvent::FriendOffline(event) => {
+            process_friend_offline_event(observer_user_id, event, received_at, offline_expiry)
+                .await?
         }
-        _ => {
-            log::warn!("Unknown event type: {}", event_type);
+        WebsocketEvent::FriendUpdate(event) => process_friend_update_event(event).await?,
+        WebsocketEvent::FriendLocation(event) => {
+            process_friend_location_event(observer_user_id, event, received_at).await?
+        }
+        WebsocketEvent::Notification(event) => process_notification_event(event).await?,
+        WebsocketEvent::NotificationV2(event) => process_notification_v2_event(event).await?,
+        WebsocketEvent::SeeNotification(notification_id) => {
+            log::info!("Notification seen: {}", notification_id);
+        }
+        WebsocketEvent::HideNotification(notification_id) => {
+            log::info!("Notification hidden: {}", notification_id);
+        }
+        WebsocketEvent::ResponseNotification(event) => {
+            log::info!(
+                "Notification {} responded with {}",
+                event.notification_id,
+                event.response_id
+            );
+        }
+        WebsocketEvent::UserUpdate(event) => process_user_update_event(event).await?,
+        WebsocketEvent::UserLocation(event) => process_user_location_event(event).await?,
+        WebsocketEvent::UserBadgeAssigned(event) => {
+            log::info!(
+                "Badge assigned: {}",
+                event.badge["badgeName"].as_str().unwrap_or("Unknown")
+            );
+        }
+        WebsocketEvent::ContentRefresh(event) => {
+            log::info!(
+                "Content refresh: {} {}",
+                event.content_type,
+                event.action_type.as_deref().unwrap_or("")
+            );
+        }
+        WebsocketEvent::GroupJoined(event) => log::info!("Joined group: {}", event.group_id),
+        WebsocketEvent::GroupLeft(event) => log::info!("Left group: {}", event.group_id),
+        WebsocketEvent::GroupMemberUpdated(event) => {
+            log::info!(
+                "Group member updated: {} in {}",
+                event.member.user_id,
+                event.member.group_id
+            );
+        }
+        WebsocketEvent::InstanceQueueJoined(event) => {
+            log::info!(
+                "Joined queue for {} at position {}",
+                event.instance_location,
+                event.position
+            );
+        }
+        WebsocketEvent::InstanceQueueReady(event) => {
+            log::info!("Queue ready for {}", event.instance_location);
         }
     }
 
-    Ok(())
+    Ok(EventOutcome::Processed)
 }
 
-async fn process_friend_add_event(event: FriendAddEvent) -> Result<()> {
+async fn process_friend_add_event(
+    observer_user_id: Option<&str>,
+    event: FriendAddEvent,
+) -> Result<()> {
     log::info!("Friend added: {}", event.user.display_name);
 
     if let Err(e) = user_service::upsert_user(&event.user).await {
         log::error!("Failed to upsert user: {}", e);
     }
 
-    // more methods?
+    if let Some(owner_user_id) = observer_user_id {
+        if let Err(e) = friendship_service::open_friendship(owner_user_id, &event.user_id).await {
+            log::error!("Failed to open friendship: {}", e);
+        }
+    }
+
+    GLOBAL_EVENT_BUS.publish(DomainEvent::FriendAdded {
+        account_user_id: observer_user_id.map(str::to_string),
+        user_id: event.user_id,
+    });
 
     Ok(())
 }
 
-async fn process_friend_delete_event(event: FriendDeleteEvent) -> Result<()> {
+async fn process_friend_delete_event(
+    observer_user_id: Option<&str>,
+    event: FriendDeleteEvent,
+) -> Result<()> {
     log::info!("Friend deleted: {}", event.user.display_name);
 
     if let Err(e) = user_service::upsert_user(&event.user).await {
         log::error!("Failed to upsert user: {}", e);
     }
 
+    if let Some(owner_user_id) = observer_user_id {
+        if let Err(e) = friendship_service::close_friendship(owner_user_id, &event.user.id).await {
+            log::error!("Failed to close friendship: {}", e);
+        }
+    }
+
+    GLOBAL_EVENT_BUS.publish(DomainEvent::FriendRemoved {
+        account_user_id: observer_user_id.map(str::to_string),
+        user_id: event.user.id,
+    });
+
     Ok(())
 }
 
-async fn process_friend_online_event(event: FriendOnlineEvent) -> Result<()> {
+async fn process_friend_online_event(
+    observer_user_id: Option<&str>,
+    event: FriendOnlineEvent,
+    received_at: DateTime<Utc>,
+) -> Result<()> {
     log::info!(
         "Friend online: {} at {}",
         event.user.display_name,
         event.location.as_deref().unwrap_or("Unknown")
     );
 
+    // Coming back within the grace period is not a presence change.
+    if !cancel_pending_offline(observer_user_id, &event.user_id) {
+        publish_presence(
+            observer_user_id,
+            &event.user_id,
+            PresenceState::Online,
+            received_at,
+        );
+    }
+
     if let Err(e) = user_service::upsert_user(&event.user).await {
         log::error!("Failed to upsert user: {}", e);
     }
 
+    if let Err(e) = session_service::open_session(
+        observer_user_id,
+        &event.user_id,
+        event.platform.clone(),
+        &UserState::Online.to_string(),
+        received_at,
+    )
+    .await
+    {
+        log::error!("Failed to open session: {}", e);
+    }
+
+    if let Some(location) = event.parsed_location() {
+        if let Err(e) = location_service::record_location(
+            observer_user_id,
+            &event.user_id,
+            &location,
+            None,
+            received_at,
+        )
+        .await
+        {
+            log::error!("Failed to record location: {}", e);
+        }
+        publish_location(observer_user_id, &event.user_id, location);
+    }
+
     Ok(())
 }
 
-async fn process_friend_active_event(event: FriendActiveEvent) -> Result<()> {
+async fn process_friend_active_event(
+    observer_user_id: Option<&str>,
+    event: FriendActiveEvent,
+    received_at: DateTime<Utc>,
+) -> Result<()> {
     log::info!("Friend active: {}", event.user.display_name);
 
+    cancel_pending_offline(observer_user_id, &event.user_id);
+    publish_presence(
+        observer_user_id,
+        &event.user_id,
+        PresenceState::Active,
+        received_at,
+    );
+
     if let Err(e) = user_service::upsert_user(&event.user).await {
         log::error!("Failed to upsert user: {}", e);
     }
 
+    if let Err(e) = session_service::open_session(
+        observer_user_id,
+        &event.user_id,
+        event.platform.clone(),
+        &UserState::Active.to_string(),
+        received_at,
+    )
+    .await
+    {
+        log::error!("Failed to open session: {}", e);
+    }
+
     Ok(())
 }
 
-async fn process_friend_offline_event(event: FriendOfflineEvent) -> Result<()> {
+async fn process_friend_offline_event(
+    observer_user_id: Option<&str>,
+    event: FriendOfflineEvent,
+    received_at: DateTime<Utc>,
+    offline_expiry: OfflineExpiry,
+) -> Result<()> {
     log::info!("Friend offline: {}", event.user_id);
 
-    // only offline time? pending??
+    let since = received_at;
+    let grace = pending_offline_grace();
+    if grace.is_zero() {
+        commit_friend_offline(observer_user_id, &event.user_id, since).await;
+        return Ok(());
+    }
+
+    let observer = observer_user_id.map(str::to_string);
+    let user_id = event.user_id.clone();
+    // The window runs from when the frame arrived, not from when it was processed.
+    let remaining = (since + grace - Utc::now()).to_std().unwrap_or_default();
+    // The timer takes the same lock, so even one that has already expired (a backlogged or
+    // replayed frame) only looks for the entry once it is in place.
+    let previous = {
+        let mut pending = PENDING_OFFLINE.lock().unwrap();
+        let task = (offline_expiry == OfflineExpiry::Timer).then(|| {
+            let observer = observer.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(remaining).await;
+
+                let still_pending = {
+                    let mut pending = PENDING_OFFLINE.lock().unwrap();
+                    let pending = pending.entry(observer.clone()).or_default();
+                    match pending.get(&user_id) {
+                        Some(p) if p.since == since => pending.remove(&user_id).is_some(),
+                        _ => false,
+                    }
+                };
+                if still_pending {
+                    commit_friend_offline(observer.as_deref(), &user_id, since).await;
+                }
+            })
+        });
+
+        pending.entry(observer).or_default().insert(
+            event.user_id.clone(),
+            PendingOffline {
+                since,
+                abort_handle: task.map(|task| task.abort_handle()),
+            },
+        )
+    };
+    if let Some(previous) = previous {
+        previous.cancel_timer();
+    }
+
+    log::info!(
+        "Holding offline for {} for {}s",
+        event.user_id,
+        grace.as_secs()
+    );
 
     Ok(())
 }
 
+/// Drops a pending offline for `user_id`, returning whether one was cancelled.
+fn cancel_pending_offline(observer_user_id: Option<&str>, user_id: &str) -> bool {
+    let pending = PENDING_OFFLINE
+        .lock()
+        .unwrap()
+        .get_mut(&observer_user_id.map(str::to_string))
+        .and_then(|pending| pending.remove(user_id));
+    match pending {
+        Some(pending) => {
+            pending.cancel_timer();
+            log::info!(
+                "Friend {} came back within the grace period, offline since {} discarded",
+                user_id,
+                pending.since
+            );
+            true
+        }
+        None => false,
+    }
+}
+
+/// Commits every offline `observer_user_id` still holds inside the grace window right away,
+/// e.g. when its pipeline shuts down or at the end of a replay, where the grace timers would
+/// otherwise never fire. Other accounts keep their pending offlines.
+pub async fn flush_pending_offline(observer_user_id: Option<&str>) {
+    let pending = PENDING_OFFLINE
+        .lock()
+        .unwrap()
+        .remove(&observer_user_id.map(str::to_string))
+        .unwrap_or_default();
+
+    for (user_id, pending) in pending {
+        pending.cancel_timer();
+        commit_friend_offline(observer_user_id, &user_id, pending.since).await;
+    }
+}
+
+/// Commits the offlines of `observer_user_id` whose grace window had passed by `now`, for
+/// callers processing frames with `OfflineExpiry::Manual`.
+pub async fn commit_expired_offline(observer_user_id: Option<&str>, now: DateTime<Utc>) {
+    let grace = pending_offline_grace();
+    let expired: Vec<(String, PendingOffline)> = {
+        let mut pending = PENDING_OFFLINE.lock().unwrap();
+        let Some(pending) = pending.get_mut(&observer_user_id.map(str::to_string)) else {
+            return;
+        };
+        let expired_ids: Vec<String> = pending
+            .iter()
+            .filter(|(_, p)| p.since + grace <= now)
+            .map(|(user_id, _)| user_id.clone())
+            .collect();
+        expired_ids
+            .into_iter()
+            .filter_map(|user_id| pending.remove_entry(&user_id))
+            .collect()
+    };
+
+    for (user_id, pending) in expired {
+        pending.cancel_timer();
+        commit_friend_offline(observer_user_id, &user_id, pending.since).await;
+    }
+}
+
+async fn commit_friend_offline(
+    observer_user_id: Option<&str>,
+    user_id: &str,
+    since: DateTime<Utc>,
+) {
+    log::info!("Committing offline for {}", user_id);
+
+    if let Err(e) = session_service::close_session(observer_user_id, user_id, since).await {
+        log::error!("Failed to close session: {}", e);
+    }
+
+    if let Err(e) = location_service::record_location(
+        observer_user_id,
+        user_id,
+        &Location::Offline,
+        None,
+        since,
+    )
+    .await
+    {
+        log::error!("Failed to record location: {}", e);
+    }
+
+    publish_presence(observer_user_id, user_id, PresenceState::Offline, since);
+}
+
+fn publish_presence(
+    observer_user_id: Option<&str>,
+    user_id: &str,
+    state: PresenceState,
+    at: DateTime<Utc>,
+) {
+    GLOBAL_EVENT_BUS.publish(DomainEvent::PresenceChanged {
+        account_user_id: observer_user_id.map(str::to_string),
+        user_id: user_id.to_string(),
+        state,
+        at,
+    });
+}
+
+fn publish_location(observer_user_id: Option<&str>, user_id: &str, location: Location) {
+    GLOBAL_EVENT_BUS.publish(DomainEvent::LocationChanged {
+        account_user_id: observer_user_id.map(str::to_string),
+        user_id: user_id.to_string(),
+        location,
+    });
+}
+
 async fn process_friend_update_event(event: FriendUpdateEvent) -> Result<()> {
     log::info!("Friend updated: {}", event.user.display_name);
 
@@ -114,16 +613,113 @@ async fn process_friend_update_event(event: FriendUpdateEvent) -> Result<()> {
     Ok(())
 }
 
-async fn process_friend_location_event(event: FriendLocationEvent) -> Result<()> {
+async fn process_friend_location_event(
+    observer_user_id: Option<&str>,
+    event: FriendLocationEvent,
+    received_at: DateTime<Utc>,
+) -> Result<()> {
     log::info!(
         "Friend location: {} at {}",
         event.user.display_name,
         event.location.as_deref().unwrap_or("Unknown")
     );
 
+    cancel_pending_offline(observer_user_id, &event.user_id);
+
     if let Err(e) = user_service::upsert_user(&event.user).await {
         log::error!("Failed to upsert user: {}", e);
     }
 
+    // A location update implies the friend is in-game, which also reopens sessions
+    // that were closed while the pipeline was down.
+    if let Err(e) = session_service::open_session(
+        observer_user_id,
+        &event.user_id,
+        Some(event.user.last_platform.clone()).filter(|p| !p.is_empty()),
+        &UserState::Online.to_string(),
+        received_at,
+    )
+    .await
+    {
+        log::error!("Failed to open session: {}", e);
+    }
+
+    if let Some(location) = event.parsed_location() {
+        let world_id = if location.is_traveling() {
+            event
+                .parsed_traveling_to_location()
+                .and_then(|l| l.world_id().map(str::to_string))
+                .or_else(|| Some(event.world_id.clone()).filter(|w| !w.is_empty()))
+        } else {
+            None
+        };
+
+        if let Err(e) = location_service::record_location(
+            observer_user_id,
+            &event.user_id,
+            &location,
+            world_id,
+            received_at,
+        )
+        .await
+        {
+            log::error!("Failed to record location: {}", e);
+        }
+        publish_location(observer_user_id, &event.user_id, location);
+    }
+
+    Ok(())
+}
+
+async fn process_notification_event(event: NotificationEvent) -> Result<()> {
+    log::info!(
+        "Notification {} ({}) from {}: {}",
+        event.id,
+        event.notification_type,
+        event
+            .sender_username
+            .as_deref()
+            .unwrap_or(&event.sender_user_id),
+        event.message
+    );
+
+    Ok(())
+}
+
+async fn process_notification_v2_event(event: NotificationV2Event) -> Result<()> {
+    log::info!(
+        "Notification {} ({}): {}",
+        event.id,
+        event.notification_type,
+        event
+            .title
+            .as_deref()
+            .or(event.message.as_deref())
+            .unwrap_or("")
+    );
+
+    Ok(())
+}
+
+async fn process_user_update_event(event: UserUpdateEvent) -> Result<()> {
+    log::info!(
+        "Current user updated: {} ({})",
+        event.user.display_name,
+        event.user_id
+    );
+
+    Ok(())
+}
+
+async fn process_user_location_event(event: UserLocationEvent) -> Result<()> {
+    let location = event.parsed_location();
+    log::info!(
+        "Current user location: {}",
+        location
+            .as_ref()
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "Unknown".to_string())
+    );
+
     Ok(())
 }
@@ -0,0 +1,139 @@
+use crate::database::get_db_connection;
+use crate::entities::{friendships, prelude::*};
+use chrono::Utc;
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::sea_query::Expr;
+use sea_orm::*;
+use std::collections::HashSet;
+use vrchatapi::models::LimitedUser;
+
+/// Opens a friendship row for `owner_user_id`, reusing the active one if it already exists.
+pub async fn open_friendship(
+    owner_user_id: &str,
+    friend_user_id: &str,
+) -> Result<friendships::Model, DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    open_friendship_with(&db, owner_user_id, friend_user_id).await
+}
+
+/// Closes the active friendship row between `owner_user_id` and `friend_user_id`, if any.
+pub async fn close_friendship(owner_user_id: &str, friend_user_id: &str) -> Result<u64, DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    close_friendships_with(&db, owner_user_id, [friend_user_id.to_string()]).await
+}
+
+/// Brings the friendships of `owner_user_id` in line with the friend list returned by the API.
+///
+/// Every friend must already be in `users` (see `user_service::sync_friends`) for the foreign
+/// key to hold. Friendships missing a row are opened and active rows for users no longer in the
+/// list are closed, which catches unfriends that happened while no pipeline was connected.
+pub async fn reconcile_friendships(
+    owner_user_id: &str,
+    friends: &[LimitedUser],
+) -> Result<(), DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    let txn = db.begin().await?;
+
+    let active_ids = Friendships::find()
+        .filter(friendships::Column::OwnerUserId.eq(owner_user_id))
+        .filter(friendships::Column::IsActive.eq(true))
+        .all(&txn)
+        .await?
+        .into_iter()
+        .map(|f| f.friend_user_id)
+        .collect::<HashSet<_>>();
+    let friend_ids = friends.iter().map(|f| f.id.clone()).collect::<HashSet<_>>();
+
+    let mut opened = 0;
+    for friend_id in friend_ids.difference(&active_ids) {
+        open_friendship_with(&txn, owner_user_id, friend_id).await?;
+        opened += 1;
+    }
+
+    let removed = active_ids
+        .difference(&friend_ids)
+        .cloned()
+        .collect::<Vec<_>>();
+    let closed = close_friendships_with(&txn, owner_user_id, removed).await?;
+
+    txn.commit().await?;
+
+    log::info!(
+        "Reconciled friendships for {}: {} friends, {} opened, {} closed",
+        owner_user_id,
+        friend_ids.len(),
+        opened,
+        closed
+    );
+
+    Ok(())
+}
+
+async fn open_friendship_with<C: ConnectionTrait>(
+    db: &C,
+    owner_user_id: &str,
+    friend_user_id: &str,
+) -> Result<friendships::Model, DbErr> {
+    let existing = Friendships::find()
+        .filter(friendships::Column::OwnerUserId.eq(owner_user_id))
+        .filter(friendships::Column::FriendUserId.eq(friend_user_id))
+        .filter(friendships::Column::IsActive.eq(true))
+        .one(db)
+        .await?;
+    if let Some(existing) = existing {
+        return Ok(existing);
+    }
+
+    log::info!("Opening friendship {} -> {}", owner_user_id, friend_user_id);
+
+    friendships::ActiveModel {
+        owner_user_id: Set(owner_user_id.to_string()),
+        friend_user_id: Set(friend_user_id.to_string()),
+        is_active: Set(true),
+        friended_at: Set(Some(Utc::now().into())),
+        unfriended_at: Set(None),
+        ..Default::default()
+    }
+    .insert(db)
+    .await
+}
+
+async fn close_friendships_with<C: ConnectionTrait>(
+    db: &C,
+    owner_user_id: &str,
+    friend_user_ids: impl IntoIterator<Item = String>,
+) -> Result<u64, DbErr> {
+    let friend_user_ids = friend_user_ids.into_iter().collect::<Vec<_>>();
+    if friend_user_ids.is_empty() {
+        return Ok(0);
+    }
+
+    log::info!(
+        "Closing friendship(s) {} -> {:?}",
+        owner_user_id,
+        friend_user_ids
+    );
+
+    let result = Friendships::update_many()
+        .col_expr(friendships::Column::IsActive, Expr::value(false))
+        .col_expr(
+            friendships::Column::UnfriendedAt,
+            Expr::value(Some(DateTimeWithTimeZone::from(Utc::now()))),
+        )
+        .filter(friendships::Column::OwnerUserId.eq(owner_user_id))
+        .filter(friendships::Column::FriendUserId.is_in(friend_user_ids))
+        .filter(friendships::Column::IsActive.eq(true))
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected)
+}
@@ -0,0 +1,67 @@
+use crate::conversions::location_history_model;
+use crate::database::get_db_connection;
+use crate::entities::{prelude::*, user_location_history};
+use crate::models::location::Location;
+use crate::services::observed_by;
+use chrono::{DateTime, Utc};
+use sea_orm::*;
+
+/// Appends a location history row for `user_id` at `recorded_at` unless it matches the
+/// user's latest row seen by `observer_user_id`.
+///
+/// `world_id` overrides the world derived from `location`, which is used to keep the
+/// destination world of a `traveling` transition.
+pub async fn record_location(
+    observer_user_id: Option<&str>,
+    user_id: &str,
+    location: &Location,
+    world_id: Option<String>,
+    recorded_at: DateTime<Utc>,
+) -> Result<Option<user_location_history::Model>, DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    let location_str = location.to_string();
+    let world_id = world_id.or_else(|| location.world_id().map(str::to_string));
+
+    let previous = latest_location(&db, observer_user_id, user_id).await?;
+    if let Some(previous) = &previous {
+        if previous.location.as_deref() == Some(location_str.as_str())
+            && previous.world_id == world_id
+        {
+            log::debug!("Location unchanged for {}: {}", user_id, location_str);
+            return Ok(None);
+        }
+    } else if location.is_offline() {
+        // Nothing to close out, and the user may not exist in `users` yet.
+        return Ok(None);
+    }
+
+    let mut history_model = location_history_model(user_id, location);
+    history_model.world_id = Set(world_id);
+    history_model.recorded_at = Set(recorded_at.into());
+    history_model.observer_user_id = Set(observer_user_id.map(str::to_string));
+
+    let inserted = history_model.insert(&db).await?;
+    log::info!("Recorded location for {}: {}", user_id, location_str);
+
+    Ok(Some(inserted))
+}
+
+pub async fn latest_location<C: ConnectionTrait>(
+    db: &C,
+    observer_user_id: Option<&str>,
+    user_id: &str,
+) -> Result<Option<user_location_history::Model>, DbErr> {
+    UserLocationHistory::find()
+        .filter(user_location_history::Column::UserId.eq(user_id))
+        .filter(observed_by(
+            user_location_history::Column::ObserverUserId,
+            observer_user_id,
+        ))
+        .order_by_desc(user_location_history::Column::RecordedAt)
+        .order_by_desc(user_location_history::Column::Id)
+        .one(db)
+        .await
+}
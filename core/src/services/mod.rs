@@ -1,2 +1,17 @@
+pub mod dead_letter_service;
 pub mod event_service;
+pub mod friendship_service;
+pub mod location_service;
+pub mod pipeline_event_service;
+pub mod session_service;
 pub mod user_service;
+
+use sea_orm::{ColumnTrait, Condition};
+
+/// Matches rows observed through `observer_user_id`, or rows with no observer for `None`.
+pub(crate) fn observed_by(column: impl ColumnTrait, observer_user_id: Option<&str>) -> Condition {
+    match observer_user_id {
+        Some(observer_user_id) => Condition::all().add(column.eq(observer_user_id)),
+        None => Condition::all().add(column.is_null()),
+    }
+}
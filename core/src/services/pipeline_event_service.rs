@@ -0,0 +1,115 @@
+use crate::config;
+use crate::database::get_db_connection;
+use crate::entities::{pipeline_events, prelude::*};
+use crate::services::event_service::EventOutcome;
+use chrono::{DateTime, Utc};
+use sea_orm::*;
+use serde_json::Value;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+const DEFAULT_PIPELINE_EVENT_RETENTION_DAYS: i64 = 30;
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+static LAST_PRUNE: LazyLock<Mutex<Option<Instant>>> = LazyLock::new(|| Mutex::new(None));
+
+/// How many days archived frames are kept, from `PIPELINE_EVENT_RETENTION_DAYS`.
+///
+/// Values that are not positive fall back to the default.
+pub fn pipeline_event_retention_days() -> i64 {
+    config::positive_env_var(
+        "PIPELINE_EVENT_RETENTION_DAYS",
+        DEFAULT_PIPELINE_EVENT_RETENTION_DAYS,
+    )
+}
+
+/// A processed pipeline frame ready to be archived.
+#[derive(Debug, Clone)]
+pub struct ArchivedEvent {
+    pub event_type: String,
+    pub content: Option<Value>,
+    pub received_at: DateTime<Utc>,
+    pub outcome: EventOutcome,
+    pub error: Option<String>,
+    /// The account whose pipeline delivered the frame.
+    pub observer_user_id: Option<String>,
+}
+
+impl From<ArchivedEvent> for pipeline_events::ActiveModel {
+    fn from(event: ArchivedEvent) -> Self {
+        Self {
+            event_type: Set(event.event_type),
+            content: Set(event.content),
+            outcome: Set(event.outcome.as_str().to_string()),
+            error: Set(event.error),
+            received_at: Set(event.received_at.into()),
+            observer_user_id: Set(event.observer_user_id),
+            ..Default::default()
+        }
+    }
+}
+
+/// Stores a received pipeline frame with the result of processing it.
+pub async fn archive_event(event: ArchivedEvent) -> Result<(), DbErr> {
+    archive_events(vec![event]).await
+}
+
+/// Stores a batch of pipeline frames in a single insert.
+pub async fn archive_events(events: Vec<ArchivedEvent>) -> Result<(), DbErr> {
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    PipelineEvents::insert_many(events.into_iter().map(pipeline_events::ActiveModel::from))
+        .exec_without_returning(&db)
+        .await?;
+
+    if prune_due() {
+        if let Err(e) = prune_events(&db).await {
+            log::error!("Failed to prune pipeline events: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+fn prune_due() -> bool {
+    let mut last_prune = LAST_PRUNE.lock().unwrap();
+    if last_prune.is_some_and(|t| t.elapsed() < PRUNE_INTERVAL) {
+        return false;
+    }
+    *last_prune = Some(Instant::now());
+    true
+}
+
+async fn prune_events<C: ConnectionTrait>(db: &C) -> Result<u64, DbErr> {
+    let retention_days = pipeline_event_retention_days();
+    let cutoff = chrono::Duration::try_days(retention_days)
+        .and_then(|retention| Utc::now().checked_sub_signed(retention));
+    let Some(cutoff) = cutoff else {
+        log::warn!(
+            "PIPELINE_EVENT_RETENTION_DAYS {} is out of range, not pruning pipeline events",
+            retention_days
+        );
+        return Ok(0);
+    };
+
+    let result = PipelineEvents::delete_many()
+        .filter(pipeline_events::Column::ReceivedAt.lt(cutoff))
+        .exec(db)
+        .await?;
+
+    if result.rows_affected > 0 {
+        log::info!(
+            "Pruned {} pipeline event(s) older than {} days",
+            result.rows_affected,
+            retention_days
+        );
+    }
+
+    Ok(result.rows_affected)
+}
@@ -0,0 +1,147 @@
+use crate::database::get_db_connection;
+use crate::entities::{friend_sessions, prelude::*};
+use crate::services::observed_by;
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::prelude::DateTimeWithTimeZone;
+use sea_orm::sea_query::Expr;
+use sea_orm::*;
+
+/// Opens a presence session for `user_id` as seen by `observer_user_id` at `seen_at`, or
+/// refreshes the state of the one already open.
+pub async fn open_session(
+    observer_user_id: Option<&str>,
+    user_id: &str,
+    platform: Option<String>,
+    state: &str,
+    seen_at: DateTime<Utc>,
+) -> Result<friend_sessions::Model, DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    let now: DateTimeWithTimeZone = seen_at.into();
+
+    if let Some(open) = find_open_session(&db, observer_user_id, user_id).await? {
+        let mut session: friend_sessions::ActiveModel = open.into();
+        session.state = Set(state.to_string());
+        if platform.is_some() {
+            session.platform = Set(platform);
+        }
+        session.last_seen_at = Set(now);
+        return session.update(&db).await;
+    }
+
+    log::info!("Opening {} session for {}", state, user_id);
+
+    friend_sessions::ActiveModel {
+        user_id: Set(user_id.to_string()),
+        platform: Set(platform),
+        state: Set(state.to_string()),
+        started_at: Set(now),
+        last_seen_at: Set(now),
+        ended_at: Set(None),
+        observer_user_id: Set(observer_user_id.map(str::to_string)),
+        ..Default::default()
+    }
+    .insert(&db)
+    .await
+}
+
+/// Closes the open session for `user_id` at `ended_at`, returning it if there was one.
+pub async fn close_session(
+    observer_user_id: Option<&str>,
+    user_id: &str,
+    ended_at: DateTime<Utc>,
+) -> Result<Option<friend_sessions::Model>, DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    let Some(open) = find_open_session(&db, observer_user_id, user_id).await? else {
+        return Ok(None);
+    };
+
+    let ended_at: DateTimeWithTimeZone = ended_at.into();
+    let mut session: friend_sessions::ActiveModel = open.into();
+    session.last_seen_at = Set(ended_at);
+    session.ended_at = Set(Some(ended_at));
+    let closed = session.update(&db).await?;
+
+    log::info!(
+        "Closed session for {} after {}s",
+        user_id,
+        (ended_at - closed.started_at).num_seconds()
+    );
+
+    Ok(Some(closed))
+}
+
+/// Ends every session `observer_user_id` left open in a previous run at the last time its
+/// user was seen. Sessions observed by other accounts are still live and left alone.
+///
+/// Presence events missed while no pipeline was connected cannot be recovered, so the
+/// last observed activity is the best available end time.
+pub async fn close_interrupted_sessions(observer_user_id: Option<&str>) -> Result<u64, DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    let result = FriendSessions::update_many()
+        .col_expr(
+            friend_sessions::Column::EndedAt,
+            Expr::col(friend_sessions::Column::LastSeenAt).into(),
+        )
+        .filter(friend_sessions::Column::EndedAt.is_null())
+        .filter(observed_by(
+            friend_sessions::Column::ObserverUserId,
+            observer_user_id,
+        ))
+        .exec(&db)
+        .await?;
+
+    if result.rows_affected > 0 {
+        log::info!("Closed {} interrupted session(s)", result.rows_affected);
+    }
+
+    Ok(result.rows_affected)
+}
+
+/// Total time `user_id` has spent online as seen by `observer_user_id`, counting an open
+/// session up to now.
+pub async fn play_time(observer_user_id: Option<&str>, user_id: &str) -> Result<Duration, DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    let now: DateTimeWithTimeZone = Utc::now().into();
+    let sessions = FriendSessions::find()
+        .filter(friend_sessions::Column::UserId.eq(user_id))
+        .filter(observed_by(
+            friend_sessions::Column::ObserverUserId,
+            observer_user_id,
+        ))
+        .all(&db)
+        .await?;
+
+    Ok(sessions
+        .iter()
+        .map(|s| s.ended_at.unwrap_or(now) - s.started_at)
+        .sum())
+}
+
+async fn find_open_session<C: ConnectionTrait>(
+    db: &C,
+    observer_user_id: Option<&str>,
+    user_id: &str,
+) -> Result<Option<friend_sessions::Model>, DbErr> {
+    FriendSessions::find()
+        .filter(friend_sessions::Column::UserId.eq(user_id))
+        .filter(observed_by(
+            friend_sessions::Column::ObserverUserId,
+            observer_user_id,
+        ))
+        .filter(friend_sessions::Column::EndedAt.is_null())
+        .order_by_desc(friend_sessions::Column::StartedAt)
+        .one(db)
+        .await
+}
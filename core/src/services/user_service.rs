@@ -1,14 +1,28 @@
 use crate::database::get_db_connection;
-use crate::entities::{prelude::*, users};
-use sea_orm::sea_query::OnConflict;
+use crate::entities::{friendships, prelude::*, user_attribute_history, users};
+use chrono::Utc;
+use sea_orm::sea_query::{Expr, OnConflict, Query};
 use sea_orm::*;
-use vrchatapi::models::User;
+use vrchatapi::models::{LimitedUser, User};
+
+/// Attributes diffed into `user_attribute_history`, in the order `attribute_changes` expects.
+const TRACKED_ATTRIBUTES: [&str; 5] = [
+    "display_name",
+    "bio",
+    "status",
+    "status_description",
+    "pronouns",
+];
 
 pub async fn upsert_user(api_user: &User) -> Result<users::Model, DbErr> {
     let db = get_db_connection()
         .await
         .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
 
+    let txn = db.begin().await?;
+
+    let previous = Users::find_by_id(&api_user.id).one(&txn).await?;
+
     let user_model = users::ActiveModel::from(api_user.clone());
 
     let insert_result = Users::insert(user_model)
@@ -23,16 +37,198 @@ pub async fn upsert_user(api_user: &User) -> Result<users::Model, DbErr> {
                     users::Column::IsFriend,
                     users::Column::LastLogin,
                     users::Column::Pronouns,
+                    users::Column::ProfilePicOverride,
+                    users::Column::UserIcon,
+                    users::Column::LastApiUpdateAt,
+                    users::Column::RawData,
                 ])
                 .to_owned(),
         )
-        .exec(&db)
+        .exec(&txn)
         .await?;
 
     log::info!("User upsert result - ID: {}", insert_result.last_insert_id);
 
-    Users::find_by_id(&api_user.id)
-        .one(&db)
+    if let Some(previous) = &previous {
+        let changes = attribute_changes(
+            previous,
+            [
+                Some(api_user.display_name.clone()),
+                Some(api_user.bio.clone()),
+                Some(api_user.status.to_string()),
+                Some(api_user.status_description.clone()),
+                Some(api_user.pronouns.clone()),
+            ],
+        );
+        insert_attribute_changes(&txn, &api_user.id, changes).await?;
+    }
+
+    let user = Users::find_by_id(&api_user.id)
+        .one(&txn)
         .await?
-        .ok_or_else(|| DbErr::Custom("Failed to retrieve user after upsert".to_string()))
+        .ok_or_else(|| DbErr::Custom("Failed to retrieve user after upsert".to_string()))?;
+
+    txn.commit().await?;
+
+    Ok(user)
+}
+
+/// Upserts every friend of `owner_user_id` from the friends list API and clears `is_friend`
+/// for everyone who is no longer a friend of any account.
+pub async fn sync_friends(owner_user_id: &str, friends: &[LimitedUser]) -> Result<(), DbErr> {
+    let db = get_db_connection()
+        .await
+        .ok_or_else(|| DbErr::Custom("Database connection not available".to_string()))?;
+
+    let txn = db.begin().await?;
+
+    for friend in friends {
+        upsert_limited_user(&txn, friend).await?;
+    }
+
+    let friends_of_other_accounts = Query::select()
+        .column(friendships::Column::FriendUserId)
+        .from(Friendships)
+        .and_where(friendships::Column::OwnerUserId.ne(owner_user_id))
+        .and_where(friendships::Column::IsActive.eq(true))
+        .to_owned();
+
+    let unfriended = Users::update_many()
+        .col_expr(users::Column::IsFriend, Expr::value(false))
+        .filter(users::Column::IsFriend.eq(true))
+        .filter(users::Column::Id.is_not_in(friends.iter().map(|f| f.id.clone())))
+        .filter(users::Column::Id.not_in_subquery(friends_of_other_accounts))
+        .exec(&txn)
+        .await?;
+
+    txn.commit().await?;
+
+    log::info!(
+        "Synced {} friends, {} no longer friends",
+        friends.len(),
+        unfriended.rows_affected
+    );
+
+    Ok(())
+}
+
+/// Upserts a user from the friends list, leaving columns the API omitted untouched.
+async fn upsert_limited_user<C: ConnectionTrait>(
+    db: &C,
+    api_user: &LimitedUser,
+) -> Result<(), DbErr> {
+    let previous = Users::find_by_id(&api_user.id).one(db).await?;
+
+    let mut user_model = users::ActiveModel::from(api_user.clone());
+    user_model.is_friend = Set(true);
+    user_model.last_api_update_at = Set(Some(Utc::now().into()));
+
+    let mut update_columns = vec![
+        users::Column::Username,
+        users::Column::DisplayName,
+        users::Column::Status,
+        users::Column::StatusDescription,
+        users::Column::IsFriend,
+        users::Column::ProfilePicOverride,
+        users::Column::UserIcon,
+        users::Column::LastApiUpdateAt,
+    ];
+    if api_user.bio.is_some() {
+        update_columns.push(users::Column::Bio);
+    }
+    if api_user.pronouns.is_some() {
+        update_columns.push(users::Column::Pronouns);
+    }
+    if matches!(api_user.last_login, Some(Some(_))) {
+        update_columns.push(users::Column::LastLogin);
+    }
+    // The full user from `get_user` says more than a friend list entry, so it is kept.
+    if previous
+        .as_ref()
+        .is_none_or(|previous| previous.raw_data.is_none())
+    {
+        update_columns.push(users::Column::RawData);
+    }
+
+    Users::insert(user_model)
+        .on_conflict(
+            OnConflict::column(users::Column::Id)
+                .update_columns(update_columns)
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+
+    if let Some(previous) = &previous {
+        let changes = attribute_changes(
+            previous,
+            [
+                Some(api_user.display_name.clone()),
+                api_user.bio.clone(),
+                Some(api_user.status.to_string()),
+                Some(api_user.status_description.clone()),
+                api_user.pronouns.clone(),
+            ],
+        );
+        insert_attribute_changes(db, &api_user.id, changes).await?;
+    }
+
+    Ok(())
+}
+
+/// Pairs `TRACKED_ATTRIBUTES` with their previous and current values, skipping unknown ones.
+fn attribute_changes(
+    previous: &users::Model,
+    current: [Option<String>; 5],
+) -> Vec<(&'static str, String, String)> {
+    let previous_values = [
+        &previous.display_name,
+        &previous.bio,
+        &previous.status,
+        &previous.status_description,
+        &previous.pronouns,
+    ];
+
+    TRACKED_ATTRIBUTES
+        .into_iter()
+        .zip(previous_values)
+        .zip(current)
+        .filter_map(|((attribute_name, old_value), new_value)| {
+            let new_value = new_value?;
+            (*old_value != new_value).then(|| (attribute_name, old_value.clone(), new_value))
+        })
+        .collect()
+}
+
+async fn insert_attribute_changes<C: ConnectionTrait>(
+    db: &C,
+    user_id: &str,
+    changes: Vec<(&'static str, String, String)>,
+) -> Result<(), DbErr> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    log::info!(
+        "Recording {} attribute change(s) for {}",
+        changes.len(),
+        user_id
+    );
+
+    let history_models = changes
+        .into_iter()
+        .map(
+            |(attribute_name, old_value, new_value)| user_attribute_history::ActiveModel {
+                user_id: Set(user_id.to_string()),
+                attribute_name: Set(attribute_name.to_string()),
+                old_value: Set(Some(old_value)),
+                new_value: Set(Some(new_value)),
+                ..Default::default()
+            },
+        );
+    UserAttributeHistory::insert_many(history_models)
+        .exec(db)
+        .await?;
+
+    Ok(())
 }
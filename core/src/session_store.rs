@@ -0,0 +1,392 @@
+use crate::config;
+use crate::secrets::{self, SecretError, ENCRYPTED_PREFIX};
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, PoisonError, RwLock};
+
+static SESSION_PATH: LazyLock<RwLock<Option<PathBuf>>> = LazyLock::new(|| RwLock::new(None));
+
+#[derive(Debug, thiserror::Error)]
+pub enum SessionStoreError {
+    #[error("failed to access session file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("session file {path} is not a valid cookie store: {message}")]
+    Format { path: PathBuf, message: String },
+    #[error("session file {path} is encrypted but no encryption key is configured")]
+    EncryptedWithoutKey { path: PathBuf },
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+}
+
+/// Where the VRChat session cookies are kept between runs.
+pub trait SessionStore: Send + Sync {
+    /// Returns the saved cookies, or `None` when nothing was saved yet.
+    fn load(&self) -> Result<Option<CookieStore>, SessionStoreError>;
+    fn save(&self, cookies: &CookieStore) -> Result<(), SessionStoreError>;
+}
+
+/// Cookies as plain JSON, for setups without an encryption key.
+///
+/// An encrypted file is left alone rather than replaced with plaintext, so a key that is
+/// missing for one run does not throw the saved session away.
+pub struct PlainFileStore {
+    path: PathBuf,
+}
+
+impl PlainFileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl SessionStore for PlainFileStore {
+    fn load(&self) -> Result<Option<CookieStore>, SessionStoreError> {
+        let Some(contents) = read_optional(&self.path)? else {
+            return Ok(None);
+        };
+        if is_encrypted(&contents) {
+            return Err(SessionStoreError::EncryptedWithoutKey {
+                path: self.path.clone(),
+            });
+        }
+        parse_cookies(&self.path, &contents).map(Some)
+    }
+
+    fn save(&self, cookies: &CookieStore) -> Result<(), SessionStoreError> {
+        if read_optional(&self.path)?.is_some_and(|contents| is_encrypted(&contents)) {
+            return Err(SessionStoreError::EncryptedWithoutKey {
+                path: self.path.clone(),
+            });
+        }
+        let json = serialize_cookies(&self.path, cookies)?;
+        write_atomic(&self.path, json.as_bytes())
+    }
+}
+
+/// Cookies encrypted at rest with magic-crypt, keyed by `secrets::encryption_key`.
+///
+/// A plaintext file left by an older version is read once and rewritten encrypted.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key: String,
+}
+
+impl EncryptedFileStore {
+    pub fn new(path: impl Into<PathBuf>, key: String) -> Self {
+        Self {
+            path: path.into(),
+            key,
+        }
+    }
+}
+
+impl SessionStore for EncryptedFileStore {
+    fn load(&self) -> Result<Option<CookieStore>, SessionStoreError> {
+        let Some(contents) = read_optional(&self.path)? else {
+            return Ok(None);
+        };
+
+        if is_encrypted(&contents) {
+            let json = secrets::decrypt(&self.key, contents.trim())?;
+            return parse_cookies(&self.path, &json).map(Some);
+        }
+
+        let cookies = parse_cookies(&self.path, &contents)?;
+        log::info!("Encrypting plaintext session file {}", self.path.display());
+        self.save(&cookies)?;
+        Ok(Some(cookies))
+    }
+
+    fn save(&self, cookies: &CookieStore) -> Result<(), SessionStoreError> {
+        let json = serialize_cookies(&self.path, cookies)?;
+        let encrypted = secrets::encrypt(&self.key, &json);
+        write_atomic(&self.path, encrypted.as_bytes())
+    }
+}
+
+/// An encrypted store at `path` when an encryption key is configured, plaintext otherwise.
+pub fn file_session_store(path: impl Into<PathBuf>) -> Box<dyn SessionStore> {
+    let path = path.into();
+    match secrets::encryption_key() {
+        Ok(key) => Box::new(EncryptedFileStore::new(path, key)),
+        Err(SecretError::MissingKey) => {
+            log::warn!(
+                "No encryption key configured, session cookies in {} are stored in plaintext",
+                path.display()
+            );
+            Box::new(PlainFileStore::new(path))
+        }
+        Err(e) => {
+            log::error!("{}, session cookies are stored in plaintext", e);
+            Box::new(PlainFileStore::new(path))
+        }
+    }
+}
+
+/// Keeps the session in `path` instead of the default from `session_path`, e.g. in the app
+/// config dir of the desktop app. Takes effect on the next login.
+pub fn set_session_path(path: impl Into<PathBuf>) {
+    *SESSION_PATH.write().unwrap_or_else(PoisonError::into_inner) = Some(path.into());
+}
+
+/// Where the session is kept: the path given to `set_session_path`, else `COOKIES_PATH`,
+/// else `/app/cookies.json` when it exists (the docker volume), else `./cookies.json`.
+pub fn session_path() -> PathBuf {
+    if let Some(path) = SESSION_PATH
+        .read()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone()
+    {
+        return path;
+    }
+
+    match std::env::var("COOKIES_PATH") {
+        Ok(path) if !path.is_empty() => PathBuf::from(path),
+        _ if Path::new("/app/cookies.json").exists() => PathBuf::from("/app/cookies.json"),
+        _ => PathBuf::from("./cookies.json"),
+    }
+}
+
+/// Where the session of `username` is kept: `session_path` with the username appended to
+/// the file name, e.g. `cookies-alice.json`, so accounts logged in side by side never share
+/// a cookie jar.
+pub fn account_session_path(username: &str) -> PathBuf {
+    config::account_path(&session_path(), &username.to_lowercase())
+}
+
+/// The cookie jar handed to the API client, together with the store it was loaded from
+/// and is saved back to.
+pub struct PersistedSession {
+    store: Box<dyn SessionStore>,
+    cookies: Arc<CookieStoreMutex>,
+}
+
+impl PersistedSession {
+    /// Loads the saved cookies from `store`, starting with an empty jar when there are
+    /// none or they cannot be read.
+    pub fn open(store: Box<dyn SessionStore>) -> Self {
+        let cookies = match store.load() {
+            Ok(Some(cookies)) => cookies,
+            Ok(None) => CookieStore::new(None),
+            Err(e) => {
+                log::error!("Failed to load saved session, starting a new one: {}", e);
+                CookieStore::new(None)
+            }
+        };
+
+        Self {
+            store,
+            cookies: Arc::new(CookieStoreMutex::new(cookies)),
+        }
+    }
+
+    /// Opens the session at `session_path`, see `file_session_store`.
+    pub fn from_config() -> Self {
+        Self::open(file_session_store(session_path()))
+    }
+
+    /// Opens the session of `username` at `account_session_path`, see `file_session_store`.
+    ///
+    /// Until that file exists, the single session kept by older versions is moved into it.
+    pub fn for_account(username: &str) -> Self {
+        let path = account_session_path(username);
+        let store = file_session_store(&path);
+        if !path.exists() {
+            let legacy = legacy_session_paths()
+                .into_iter()
+                .find(|legacy| legacy != &path && legacy.is_file());
+            if let Some(legacy) = legacy {
+                if let Err(e) = migrate_legacy_session(&legacy, store.as_ref()) {
+                    log::error!("Failed to move session file {}: {}", legacy.display(), e);
+                }
+            }
+        }
+        Self::open(store)
+    }
+
+    /// The jar to install as the `reqwest` cookie provider.
+    pub fn cookies(&self) -> Arc<CookieStoreMutex> {
+        self.cookies.clone()
+    }
+
+    pub fn save(&self) -> Result<(), SessionStoreError> {
+        // A panic elsewhere while holding the jar does not make its cookies invalid.
+        let cookies = self.cookies.lock().unwrap_or_else(PoisonError::into_inner);
+        self.store.save(&cookies)
+    }
+}
+
+/// Where sessions were kept before each account had its own file: `session_path`, and the
+/// `/app/cookies.json` bind mount of older docker-compose setups.
+fn legacy_session_paths() -> Vec<PathBuf> {
+    vec![session_path(), PathBuf::from("/app/cookies.json")]
+}
+
+/// Saves the session in `legacy` to `store`, then removes the original so that no
+/// plaintext copy is left behind.
+fn migrate_legacy_session(
+    legacy: &Path,
+    store: &dyn SessionStore,
+) -> Result<(), SessionStoreError> {
+    let Some(contents) = read_optional(legacy)?.filter(|contents| !contents.trim().is_empty())
+    else {
+        return Ok(());
+    };
+
+    let json = if is_encrypted(&contents) {
+        let key = match secrets::encryption_key() {
+            Ok(key) => key,
+            Err(SecretError::MissingKey) => {
+                return Err(SessionStoreError::EncryptedWithoutKey {
+                    path: legacy.to_path_buf(),
+                })
+            }
+            Err(e) => return Err(e.into()),
+        };
+        secrets::decrypt(&key, contents.trim())?
+    } else {
+        contents
+    };
+    store.save(&parse_cookies(legacy, &json)?)?;
+    log::info!("Moved session file {} to its account", legacy.display());
+
+    if let Err(e) = fs::remove_file(legacy) {
+        // A file bind-mounted on its own cannot be removed, only emptied.
+        log::warn!(
+            "Could not remove {} ({}), emptying it instead",
+            legacy.display(),
+            e
+        );
+        fs::write(legacy, "").map_err(|source| SessionStoreError::Io {
+            path: legacy.to_path_buf(),
+            source,
+        })?;
+    }
+    Ok(())
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, SessionStoreError> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(SessionStoreError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+fn is_encrypted(contents: &str) -> bool {
+    contents.trim_start().starts_with(ENCRYPTED_PREFIX)
+}
+
+fn parse_cookies(path: &Path, json: &str) -> Result<CookieStore, SessionStoreError> {
+    serde_json::from_str(json).map_err(|e| SessionStoreError::Format {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })
+}
+
+fn serialize_cookies(path: &Path, cookies: &CookieStore) -> Result<String, SessionStoreError> {
+    serde_json::to_string(cookies).map_err(|e| SessionStoreError::Format {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })
+}
+
+/// Writes through a temporary file in the same directory and renames it into place, so a
+/// crash never leaves a truncated session file behind.
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), SessionStoreError> {
+    let io_error = |source| SessionStoreError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent).map_err(io_error)?;
+    }
+
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = File::create(&tmp_path).map_err(io_error)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .map_err(io_error)?;
+    }
+    file.write_all(contents).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    drop(file);
+
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        // A file bind-mounted on its own (as older docker-compose setups did with
+        // cookies.json) cannot be replaced, only rewritten in place.
+        log::warn!(
+            "Could not replace {} atomically ({}), rewriting it in place",
+            path.display(),
+            e
+        );
+        let _ = fs::remove_file(&tmp_path);
+        fs::write(path, contents).map_err(io_error)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_store_keeps_an_encrypted_file() {
+        let path =
+            std::env::temp_dir().join(format!("botan-session-store-{}.json", std::process::id()));
+        let encrypted = secrets::encrypt("key", "{}");
+        fs::write(&path, &encrypted).unwrap();
+
+        let store = PlainFileStore::new(&path);
+        assert!(matches!(
+            store.load(),
+            Err(SessionStoreError::EncryptedWithoutKey { .. })
+        ));
+        assert!(matches!(
+            store.save(&CookieStore::default()),
+            Err(SessionStoreError::EncryptedWithoutKey { .. })
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), encrypted);
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn legacy_session_is_moved_encrypted() {
+        let dir = std::env::temp_dir().join(format!("botan-session-legacy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (legacy, path) = (dir.join("cookies.json"), dir.join("cookies-alice.json"));
+        fs::write(
+            &legacy,
+            serde_json::to_string(&CookieStore::default()).unwrap(),
+        )
+        .unwrap();
+
+        let store = EncryptedFileStore::new(&path, "key".to_string());
+        migrate_legacy_session(&legacy, &store).unwrap();
+
+        assert!(!legacy.exists());
+        assert!(is_encrypted(&fs::read_to_string(&path).unwrap()));
+        assert!(store.load().unwrap().is_some());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
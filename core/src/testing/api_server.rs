@@ -0,0 +1,421 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use vrchatapi::models::{CurrentUser, LimitedUser};
+
+/// Second factor the mock account demands after a password login.
+#[derive(Debug, Clone)]
+pub enum MockTwoFactor {
+    /// Accepts `code` at `/auth/twofactorauth/totp/verify`.
+    Totp(String),
+    /// Accepts `code` at `/auth/twofactorauth/emailotp/verify`.
+    EmailOtp(String),
+}
+
+/// The single account served by `MockApiServer`.
+#[derive(Debug, Clone)]
+pub struct MockAccount {
+    pub username: String,
+    pub password: String,
+    pub two_factor: Option<MockTwoFactor>,
+    pub current_user: CurrentUser,
+    pub online_friends: Vec<LimitedUser>,
+    pub offline_friends: Vec<LimitedUser>,
+}
+
+impl MockAccount {
+    pub fn new(username: &str, password: &str, user_id: &str) -> Self {
+        let current_user = CurrentUser {
+            id: user_id.to_string(),
+            username: Some(username.to_string()),
+            display_name: username.to_string(),
+            ..Default::default()
+        };
+
+        Self {
+            username: username.to_string(),
+            password: password.to_string(),
+            two_factor: None,
+            current_user,
+            online_friends: Vec::new(),
+            offline_friends: Vec::new(),
+        }
+    }
+
+    pub fn with_two_factor(mut self, two_factor: MockTwoFactor) -> Self {
+        self.two_factor = Some(two_factor);
+        self
+    }
+
+    pub fn with_friends(mut self, online: Vec<LimitedUser>, offline: Vec<LimitedUser>) -> Self {
+        self.online_friends = online;
+        self.offline_friends = offline;
+        self
+    }
+}
+
+struct ApiState {
+    account: MockAccount,
+    /// `auth` cookie values issued so far, mapped to whether 2FA was completed.
+    sessions: HashMap<String, bool>,
+    logins: usize,
+    /// How many upcoming requests are answered with 429.
+    rate_limited: u32,
+    requests: Vec<String>,
+}
+
+/// Minimal VRChat REST API on `127.0.0.1` covering login, 2FA, token checks and friends.
+///
+/// Sessions are tracked through the `auth` cookie like the real API, so the
+/// `reqwest_cookie_store` setup in `auth.rs` is exercised as well.
+pub struct MockApiServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<ApiState>>,
+    task: JoinHandle<()>,
+}
+
+impl MockApiServer {
+    pub async fn start(account: MockAccount) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(ApiState {
+            account,
+            sessions: HashMap::new(),
+            logins: 0,
+            rate_limited: 0,
+            requests: Vec::new(),
+        }));
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = serve_connection(stream, state).await {
+                            log::warn!("Mock API connection failed: {}", e);
+                        }
+                    });
+                }
+            }
+        });
+
+        Ok(Self { addr, state, task })
+    }
+
+    /// Base path to hand to `VrcApiClient::set_base_url`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}/api/1", self.addr)
+    }
+
+    /// Answers the next `count` requests with `429 Too Many Requests`.
+    pub fn rate_limit_next(&self, count: u32) {
+        self.state.lock().unwrap().rate_limited = count;
+    }
+
+    /// Expires every issued session, as when VRChat invalidates the auth token.
+    pub fn revoke_sessions(&self) {
+        self.state.lock().unwrap().sessions.clear();
+    }
+
+    /// Number of logins performed with basic auth.
+    pub fn login_count(&self) -> usize {
+        self.state.lock().unwrap().logins
+    }
+
+    /// Every request so far as `"METHOD /path?query"`.
+    pub fn requests(&self) -> Vec<String> {
+        self.state.lock().unwrap().requests.clone()
+    }
+}
+
+impl Drop for MockApiServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Request {
+    method: String,
+    /// Path and query as sent, for `MockApiServer::requests`.
+    target: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn cookie(&self, name: &str) -> Option<String> {
+        self.headers.get("cookie")?.split(';').find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == name).then(|| value.to_string())
+        })
+    }
+
+    /// Username and password from an `Authorization: Basic` header, url-decoded.
+    fn basic_auth(&self) -> Option<(String, String)> {
+        let encoded = self.headers.get("authorization")?.strip_prefix("Basic ")?;
+        let decoded = String::from_utf8(BASE64_STANDARD.decode(encoded).ok()?).ok()?;
+        let (username, password) = decoded.split_once(':')?;
+        Some((
+            percent_decode_str(username)
+                .decode_utf8()
+                .ok()?
+                .into_owned(),
+            percent_decode_str(password)
+                .decode_utf8()
+                .ok()?
+                .into_owned(),
+        ))
+    }
+}
+
+struct Response {
+    status: u16,
+    body: Value,
+    set_cookies: Vec<String>,
+}
+
+impl Response {
+    fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            body,
+            set_cookies: Vec::new(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self::json(
+            status,
+            json!({ "error": { "message": message, "status_code": status } }),
+        )
+    }
+}
+
+async fn serve_connection(stream: TcpStream, state: Arc<Mutex<ApiState>>) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), query),
+        None => (target.clone(), ""),
+    };
+    let query = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+
+    let request = Request {
+        method,
+        target,
+        path,
+        query,
+        headers,
+        body,
+    };
+    let response = handle_request(&request, &mut state.lock().unwrap());
+
+    let body = response.body.to_string();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason_phrase(response.status),
+        body.len()
+    );
+    if response.status == 429 {
+        head.push_str("Retry-After: 1\r\n");
+    }
+    // Persistent like VRChat's own cookies, so a saved session survives a restart.
+    for cookie in &response.set_cookies {
+        head.push_str(&format!(
+            "Set-Cookie: {}; Max-Age=31536000; Path=/; HttpOnly\r\n",
+            cookie
+        ));
+    }
+    head.push_str("\r\n");
+
+    let mut stream = reader.into_inner();
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn handle_request(request: &Request, state: &mut ApiState) -> Response {
+    state
+        .requests
+        .push(format!("{} {}", request.method, request.target));
+
+    if state.rate_limited > 0 {
+        state.rate_limited -= 1;
+        return Response::error(429, "Too many requests");
+    }
+
+    let Some(path) = request.path.strip_prefix("/api/1") else {
+        return Response::error(404, "Not found");
+    };
+
+    match (request.method.as_str(), path) {
+        ("GET", "/auth/user") => current_user(request, state),
+        ("GET", "/auth") => verify_auth_token(request, state),
+        ("POST", "/auth/twofactorauth/totp/verify") => verify_two_factor(request, state, false),
+        ("POST", "/auth/twofactorauth/emailotp/verify") => verify_two_factor(request, state, true),
+        ("GET", "/auth/user/friends") => friends(request, state),
+        _ => Response::error(404, "Not found"),
+    }
+}
+
+/// Resolves the session for `request`, logging in with basic auth when no valid cookie
+/// is sent. Returns the session token, whether it was just issued, and whether 2FA is done.
+fn authenticate(request: &Request, state: &mut ApiState) -> Option<(String, bool, bool)> {
+    if let Some(token) = request.cookie("auth") {
+        if let Some(verified) = state.sessions.get(&token) {
+            return Some((token, false, *verified));
+        }
+    }
+
+    let (username, password) = request.basic_auth()?;
+    if username != state.account.username || password != state.account.password {
+        return None;
+    }
+
+    state.logins += 1;
+    let token = format!("authcookie_mock_{}", state.logins);
+    let verified = state.account.two_factor.is_none();
+    state.sessions.insert(token.clone(), verified);
+    Some((token, true, verified))
+}
+
+fn current_user(request: &Request, state: &mut ApiState) -> Response {
+    let Some((token, issued, verified)) = authenticate(request, state) else {
+        return Response::error(401, "\"Missing Credentials\"");
+    };
+
+    let mut response = if verified {
+        Response::json(
+            200,
+            serde_json::to_value(&state.account.current_user).unwrap_or_default(),
+        )
+    } else {
+        let methods = match state.account.two_factor {
+            Some(MockTwoFactor::EmailOtp(_)) => json!(["emailOtp"]),
+            _ => json!(["totp", "otp"]),
+        };
+        Response::json(200, json!({ "requiresTwoFactorAuth": methods }))
+    };
+
+    if issued {
+        response.set_cookies.push(format!("auth={}", token));
+    }
+    response
+}
+
+fn verify_auth_token(request: &Request, state: &mut ApiState) -> Response {
+    let session = request
+        .cookie("auth")
+        .filter(|token| state.sessions.get(token) == Some(&true));
+
+    match session {
+        Some(token) => Response::json(200, json!({ "ok": true, "token": token })),
+        None => Response::error(401, "\"Missing Credentials\""),
+    }
+}
+
+fn verify_two_factor(request: &Request, state: &mut ApiState, email: bool) -> Response {
+    let Some(token) = request
+        .cookie("auth")
+        .filter(|token| state.sessions.contains_key(token))
+    else {
+        return Response::error(401, "\"Missing Credentials\"");
+    };
+
+    let expected = match (&state.account.two_factor, email) {
+        (Some(MockTwoFactor::Totp(code)), false) | (Some(MockTwoFactor::EmailOtp(code)), true) => {
+            code.clone()
+        }
+        _ => return Response::error(400, "Two-factor method not enabled"),
+    };
+
+    let code = serde_json::from_slice::<Value>(&request.body)
+        .ok()
+        .and_then(|body| body["code"].as_str().map(str::to_string));
+    if code.as_deref() != Some(expected.as_str()) {
+        return Response::json(200, json!({ "verified": false }));
+    }
+
+    state.sessions.insert(token, true);
+    let mut response = Response::json(200, json!({ "verified": true }));
+    response
+        .set_cookies
+        .push(format!("twoFactorAuth=mock_{}", state.logins));
+    response
+}
+
+fn friends(request: &Request, state: &mut ApiState) -> Response {
+    let authorized = request
+        .cookie("auth")
+        .is_some_and(|token| state.sessions.get(&token) == Some(&true));
+    if !authorized {
+        return Response::error(401, "\"Missing Credentials\"");
+    }
+
+    let param = |name: &str, default: usize| {
+        request
+            .query
+            .get(name)
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    };
+    let offset = param("offset", 0);
+    let count = param("n", 60);
+    let offline = request.query.get("offline").is_some_and(|v| v == "true");
+
+    let friends = if offline {
+        &state.account.offline_friends
+    } else {
+        &state.account.online_friends
+    };
+    let page: Vec<&LimitedUser> = friends.iter().skip(offset).take(count).collect();
+
+    Response::json(200, serde_json::to_value(page).unwrap_or_default())
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        429 => "Too Many Requests",
+        _ => "",
+    }
+}
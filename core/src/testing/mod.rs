@@ -0,0 +1,9 @@
+//! Local stand-ins for VRChat services, for end-to-end tests from socket to database.
+//!
+//! Enabled with the `test-support` feature.
+
+pub mod api_server;
+pub mod pipeline_server;
+
+pub use api_server::{MockAccount, MockApiServer, MockTwoFactor};
+pub use pipeline_server::{MockPipelineServer, ScriptStep};
@@ -0,0 +1,196 @@
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tokio_tungstenite::tungstenite::Message;
+
+/// One action the mock pipeline performs on a connection.
+#[derive(Debug, Clone)]
+pub enum ScriptStep {
+    /// Sends `{"type": ..., "content": "<json string>"}`, double-encoded like VRChat does.
+    Event {
+        event_type: String,
+        content: Value,
+    },
+    /// Sends `content` as a plain JSON value instead of a string.
+    PlainEvent {
+        event_type: String,
+        content: Value,
+    },
+    /// Sends `text` verbatim, e.g. a malformed frame.
+    Raw(String),
+    /// Rejects the token the way VRChat does before closing.
+    AuthError,
+    Sleep(Duration),
+    /// Sends a close frame and ends the connection.
+    Close {
+        code: u16,
+        reason: String,
+    },
+    /// Drops the TCP connection without a close handshake.
+    Disconnect,
+}
+
+impl ScriptStep {
+    pub fn event(event_type: &str, content: Value) -> Self {
+        ScriptStep::Event {
+            event_type: event_type.to_string(),
+            content,
+        }
+    }
+
+    pub fn plain_event(event_type: &str, content: Value) -> Self {
+        ScriptStep::PlainEvent {
+            event_type: event_type.to_string(),
+            content,
+        }
+    }
+
+    pub fn raw(text: &str) -> Self {
+        ScriptStep::Raw(text.to_string())
+    }
+}
+
+#[derive(Default)]
+struct ServerState {
+    /// One script per incoming connection, in order.
+    scripts: VecDeque<Vec<ScriptStep>>,
+    auth_tokens: Vec<Option<String>>,
+}
+
+/// Websocket server on `127.0.0.1` that plays scripted pipeline frames.
+///
+/// Each connection plays the next queued script and then stays open, answering pings,
+/// until the client closes it. Connections beyond the queued scripts just stay open.
+pub struct MockPipelineServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<ServerState>>,
+    task: JoinHandle<()>,
+}
+
+impl MockPipelineServer {
+    pub async fn start(scripts: Vec<Vec<ScriptStep>>) -> std::io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(ServerState {
+            scripts: scripts.into(),
+            auth_tokens: Vec::new(),
+        }));
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    tokio::spawn(serve_connection(stream, state.clone()));
+                }
+            }
+        });
+
+        Ok(Self { addr, state, task })
+    }
+
+    /// Base URL to hand to `PipelineManager::with_url`.
+    pub fn url(&self) -> String {
+        format!("ws://{}/", self.addr)
+    }
+
+    /// Queues a script for the next connection that has none yet.
+    pub fn push_script(&self, script: Vec<ScriptStep>) {
+        self.state.lock().unwrap().scripts.push_back(script);
+    }
+
+    /// The `authToken` query parameter of every connection so far.
+    pub fn auth_tokens(&self) -> Vec<Option<String>> {
+        self.state.lock().unwrap().auth_tokens.clone()
+    }
+
+    pub fn connection_count(&self) -> usize {
+        self.state.lock().unwrap().auth_tokens.len()
+    }
+}
+
+impl Drop for MockPipelineServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// The handshake callback's error type is fixed by tungstenite.
+#[allow(clippy::result_large_err)]
+async fn serve_connection(stream: TcpStream, state: Arc<Mutex<ServerState>>) {
+    let mut auth_token = None;
+    let ws_stream = match tokio_tungstenite::accept_hdr_async(
+        stream,
+        |request: &Request, response: Response| {
+            auth_token = request.uri().query().and_then(|query| {
+                url::form_urlencoded::parse(query.as_bytes())
+                    .find(|(key, _)| key == "authToken")
+                    .map(|(_, value)| value.into_owned())
+            });
+            Ok(response)
+        },
+    )
+    .await
+    {
+        Ok(ws_stream) => ws_stream,
+        Err(e) => {
+            log::warn!("Mock pipeline handshake failed: {}", e);
+            return;
+        }
+    };
+
+    let script = {
+        let mut state = state.lock().unwrap();
+        state.auth_tokens.push(auth_token);
+        state.scripts.pop_front().unwrap_or_default()
+    };
+
+    let (mut write, mut read) = ws_stream.split();
+
+    for step in script {
+        let message = match step {
+            ScriptStep::Event {
+                event_type,
+                content,
+            } => json!({ "type": event_type, "content": content.to_string() }).to_string(),
+            ScriptStep::PlainEvent {
+                event_type,
+                content,
+            } => json!({ "type": event_type, "content": content }).to_string(),
+            ScriptStep::Raw(text) => text,
+            ScriptStep::AuthError => {
+                json!({ "err": "authToken doesn't correspond with an active session" }).to_string()
+            }
+            ScriptStep::Sleep(duration) => {
+                tokio::time::sleep(duration).await;
+                continue;
+            }
+            ScriptStep::Close { code, reason } => {
+                let close = CloseFrame {
+                    code: CloseCode::from(code),
+                    reason: reason.into(),
+                };
+                let _ = write.send(Message::Close(Some(close))).await;
+                return;
+            }
+            ScriptStep::Disconnect => return,
+        };
+
+        if write.send(Message::text(message)).await.is_err() {
+            return;
+        }
+    }
+
+    // Keep reading so pings are answered and the client's close is seen.
+    while let Some(Ok(message)) = read.next().await {
+        if message.is_close() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use crate::config;
+use crate::secrets::{self, SecretError};
+use crate::two_factor::{TwoFactorCode, TwoFactorMethod, TwoFactorProvider};
+use futures_util::future::BoxFuture;
+use hmac::digest::core_api::BlockSizeUser;
+use hmac::digest::Digest;
+use hmac::{Mac, SimpleHmac};
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_DIGITS: u32 = 6;
+const DEFAULT_PERIOD_SECS: u64 = 30;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TotpError {
+    #[error("invalid base32 character {0:?} in TOTP secret")]
+    InvalidBase32(char),
+    #[error("TOTP secret is empty")]
+    EmptySecret,
+    #[error("TOTP codes must have 6 to 8 digits, not {0}")]
+    InvalidDigits(u32),
+    #[error(transparent)]
+    Secret(#[from] SecretError),
+}
+
+/// The HMAC hash a TOTP secret is used with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TotpAlgorithm {
+    /// The default, and what VRChat uses.
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+/// RFC 6238 time-based one-time password generator.
+#[derive(Clone)]
+pub struct Totp {
+    secret: Vec<u8>,
+    digits: u32,
+    period: u64,
+    algorithm: TotpAlgorithm,
+}
+
+impl std::fmt::Debug for Totp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Totp")
+            .field("digits", &self.digits)
+            .field("period", &self.period)
+            .field("algorithm", &self.algorithm)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Totp {
+    /// A HMAC-SHA1 generator for `digits`-digit codes, which RFC 4226 limits to 6 to 8.
+    pub fn new(secret: Vec<u8>, digits: u32, period: u64) -> Result<Self, TotpError> {
+        if !(6..=8).contains(&digits) {
+            return Err(TotpError::InvalidDigits(digits));
+        }
+        Ok(Self {
+            secret,
+            digits,
+            // A zero period would divide by zero; a second is as short as counters go.
+            period: period.max(1),
+            algorithm: TotpAlgorithm::Sha1,
+        })
+    }
+
+    pub fn with_algorithm(mut self, algorithm: TotpAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Parses the base32 secret shown when setting up an authenticator app, with the
+    /// usual 6 digits and 30 second period. Spaces, dashes and padding are ignored.
+    pub fn from_base32(secret: &str) -> Result<Self, TotpError> {
+        let secret = decode_base32(secret)?;
+        if secret.is_empty() {
+            return Err(TotpError::EmptySecret);
+        }
+        Self::new(secret, DEFAULT_DIGITS, DEFAULT_PERIOD_SECS)
+    }
+
+    /// Loads the secret from `VRC_TOTP_SECRET`, or from the file named by
+    /// `VRC_TOTP_SECRET_FILE`, which may hold a value encrypted with `secrets::encrypt`.
+    pub fn from_env() -> Result<Option<Self>, TotpError> {
+        Self::from_env_with_prefix(None)
+    }
+
+    /// Like `from_env`, but reads `{prefix}_VRC_TOTP_SECRET` and
+    /// `{prefix}_VRC_TOTP_SECRET_FILE` when a prefix is given.
+    pub fn from_env_with_prefix(prefix: Option<&str>) -> Result<Option<Self>, TotpError> {
+        if let Some(secret) = std::env::var(config::prefixed(prefix, "VRC_TOTP_SECRET"))
+            .ok()
+            .filter(|secret| !secret.is_empty())
+        {
+            return Self::from_base32(&secret).map(Some);
+        }
+
+        match std::env::var(config::prefixed(prefix, "VRC_TOTP_SECRET_FILE")) {
+            Ok(path) if !path.is_empty() => {
+                let secret = secrets::read_secret_file(&path)?;
+                Self::from_base32(&secret).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// The code for `unix_secs`.
+    ///
+    /// ```
+    /// use botan_core::totp::Totp;
+    ///
+    /// // RFC 6238 appendix B, SHA1 with the secret "12345678901234567890".
+    /// let totp = Totp::new(b"12345678901234567890".to_vec(), 8, 30).unwrap();
+    /// assert_eq!(totp.code_at(59), "94287082");
+    /// assert_eq!(totp.code_at(1111111109), "07081804");
+    /// assert_eq!(totp.code_at(1111111111), "14050471");
+    /// assert_eq!(totp.code_at(1234567890), "89005924");
+    /// assert_eq!(totp.code_at(2000000000), "69279037");
+    /// assert_eq!(totp.code_at(20000000000), "65353130");
+    ///
+    /// let totp = Totp::from_base32("GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ").unwrap();
+    /// assert_eq!(totp.code_at(59), "287082");
+    /// ```
+    pub fn code_at(&self, unix_secs: u64) -> String {
+        let counter = (unix_secs / self.period).to_be_bytes();
+        let hash = match self.algorithm {
+            TotpAlgorithm::Sha1 => hmac::<Sha1>(&self.secret, &counter),
+            TotpAlgorithm::Sha256 => hmac::<Sha256>(&self.secret, &counter),
+            TotpAlgorithm::Sha512 => hmac::<Sha512>(&self.secret, &counter),
+        };
+
+        // Dynamic truncation, RFC 4226 section 5.3.
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+
+        let code = binary % 10u32.pow(self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    pub fn current_code(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        self.code_at(now)
+    }
+}
+
+fn hmac<D: Digest + BlockSizeUser>(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = SimpleHmac::<D>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Decodes RFC 4648 base32, case-insensitively.
+fn decode_base32(input: &str) -> Result<Vec<u8>, TotpError> {
+    let mut output = Vec::with_capacity(input.len() * 5 / 8);
+    let mut buffer: u64 = 0;
+    let mut bits = 0;
+
+    for c in input.chars() {
+        let value = match c.to_ascii_uppercase() {
+            c @ 'A'..='Z' => c as u64 - 'A' as u64,
+            c @ '2'..='7' => c as u64 - '2' as u64 + 26,
+            ' ' | '-' | '=' => continue,
+            other => return Err(TotpError::InvalidBase32(other)),
+        };
+
+        buffer = (buffer << 5) | value;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Answers authenticator-app prompts with a code generated from a stored TOTP secret, so
+/// headless logins need no human.
+pub struct TotpTwoFactor {
+    totp: Totp,
+}
+
+impl TotpTwoFactor {
+    pub fn new(totp: Totp) -> Self {
+        Self { totp }
+    }
+
+    /// See `Totp::from_env`.
+    pub fn from_env() -> Result<Option<Self>, TotpError> {
+        Self::from_env_with_prefix(None)
+    }
+
+    /// See `Totp::from_env_with_prefix`.
+    pub fn from_env_with_prefix(prefix: Option<&str>) -> Result<Option<Self>, TotpError> {
+        Ok(Totp::from_env_with_prefix(prefix)?.map(Self::new))
+    }
+}
+
+impl TwoFactorProvider for TotpTwoFactor {
+    fn provide_code<'a>(
+        &'a self,
+        methods: &'a [TwoFactorMethod],
+    ) -> BoxFuture<'a, Option<TwoFactorCode>> {
+        Box::pin(async move {
+            if !methods.contains(&TwoFactorMethod::Totp) {
+                log::warn!("VRChat asked for {:?}, not a TOTP code", methods);
+                return None;
+            }
+            Some(TwoFactorCode::new(
+                TwoFactorMethod::Totp,
+                self.totp.current_code(),
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 6238 appendix B: unix time, then the SHA1, SHA256 and SHA512 codes.
+    const VECTORS: [(u64, [&str; 3]); 6] = [
+        (59, ["94287082", "46119246", "90693936"]),
+        (1111111109, ["07081804", "68084774", "25091201"]),
+        (1111111111, ["14050471", "67062674", "99943326"]),
+        (1234567890, ["89005924", "91819424", "93441116"]),
+        (2000000000, ["69279037", "90698825", "38618901"]),
+        (20000000000, ["65353130", "77737706", "47863826"]),
+    ];
+
+    #[test]
+    fn rfc_6238_vectors() {
+        let seed = b"1234567890".repeat(7);
+        let generators = [
+            (TotpAlgorithm::Sha1, &seed[..20]),
+            (TotpAlgorithm::Sha256, &seed[..32]),
+            (TotpAlgorithm::Sha512, &seed[..64]),
+        ];
+
+        for (i, (algorithm, secret)) in generators.into_iter().enumerate() {
+            let totp = Totp::new(secret.to_vec(), 8, 30)
+                .unwrap()
+                .with_algorithm(algorithm);
+            for (unix_secs, codes) in VECTORS {
+                assert_eq!(
+                    totp.code_at(unix_secs),
+                    codes[i],
+                    "{:?} at {}",
+                    algorithm,
+                    unix_secs
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn digits_outside_six_to_eight_are_rejected() {
+        for digits in [0, 5, 9, 10] {
+            assert!(matches!(
+                Totp::new(b"secret".to_vec(), digits, 30),
+                Err(TotpError::InvalidDigits(d)) if d == digits
+            ));
+        }
+        assert!(Totp::new(b"secret".to_vec(), 6, 30).is_ok());
+    }
+}
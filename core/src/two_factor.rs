@@ -0,0 +1,240 @@
+use crate::config;
+use crate::models::EitherTwoFactorAuthCodeType;
+use futures_util::future::BoxFuture;
+use std::future::Future;
+use tokio::sync::{mpsc, oneshot};
+use vrchatapi::models::{TwoFactorAuthCode, TwoFactorEmailCode};
+
+/// Second factors VRChat can ask for in `requiresTwoFactorAuth`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TwoFactorMethod {
+    /// Authenticator app code.
+    Totp,
+    /// Code sent by email.
+    EmailOtp,
+}
+
+impl TwoFactorMethod {
+    /// Maps the names VRChat returns, ignoring methods botan cannot complete.
+    pub fn from_api(name: &str) -> Option<Self> {
+        match name {
+            "totp" => Some(TwoFactorMethod::Totp),
+            "emailOtp" => Some(TwoFactorMethod::EmailOtp),
+            _ => None,
+        }
+    }
+
+    pub fn parse_all(names: &[String]) -> Vec<Self> {
+        names
+            .iter()
+            .filter_map(|name| Self::from_api(name))
+            .collect()
+    }
+
+    /// The `two_fa_type` understood by `auth::auth_verify2_fa`.
+    pub fn verify_type(&self) -> &'static str {
+        match self {
+            TwoFactorMethod::Totp => "2fa",
+            TwoFactorMethod::EmailOtp => "email",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoFactorCode {
+    pub method: TwoFactorMethod,
+    pub code: String,
+}
+
+impl TwoFactorCode {
+    pub fn new(method: TwoFactorMethod, code: impl Into<String>) -> Self {
+        Self {
+            method,
+            code: code.into().trim().to_string(),
+        }
+    }
+}
+
+impl From<TwoFactorCode> for EitherTwoFactorAuthCodeType {
+    fn from(code: TwoFactorCode) -> Self {
+        match code.method {
+            TwoFactorMethod::Totp => {
+                EitherTwoFactorAuthCodeType::IsA(TwoFactorAuthCode { code: code.code })
+            }
+            TwoFactorMethod::EmailOtp => {
+                EitherTwoFactorAuthCodeType::IsB(TwoFactorEmailCode { code: code.code })
+            }
+        }
+    }
+}
+
+/// Supplies two-factor codes to the login flow, so each frontend can obtain them its own way.
+pub trait TwoFactorProvider: Send + Sync {
+    /// Returns a code for one of `methods`, or `None` to stop and hand the
+    /// `RequiresTwoFactorAuth` response back to the caller.
+    fn provide_code<'a>(
+        &'a self,
+        methods: &'a [TwoFactorMethod],
+    ) -> BoxFuture<'a, Option<TwoFactorCode>>;
+}
+
+/// Never supplies a code; the caller finishes 2FA itself through `auth::auth_verify2_fa`.
+pub struct DeferTwoFactor;
+
+impl TwoFactorProvider for DeferTwoFactor {
+    fn provide_code<'a>(
+        &'a self,
+        _methods: &'a [TwoFactorMethod],
+    ) -> BoxFuture<'a, Option<TwoFactorCode>> {
+        Box::pin(async { None })
+    }
+}
+
+/// Prompts on the terminal, for interactive CLI use only.
+pub struct StdinTwoFactor;
+
+impl TwoFactorProvider for StdinTwoFactor {
+    fn provide_code<'a>(
+        &'a self,
+        methods: &'a [TwoFactorMethod],
+    ) -> BoxFuture<'a, Option<TwoFactorCode>> {
+        Box::pin(async move {
+            let method = *methods.first()?;
+            let line = tokio::task::spawn_blocking(move || {
+                match method {
+                    TwoFactorMethod::Totp => println!("Please enter your 2FA code:"),
+                    TwoFactorMethod::EmailOtp => println!("Please enter the code sent by email:"),
+                }
+                let mut line = String::new();
+                std::io::stdin().read_line(&mut line).map(|_| line)
+            })
+            .await
+            .ok()?;
+
+            match line {
+                Ok(line) if !line.trim().is_empty() => Some(TwoFactorCode::new(method, line)),
+                Ok(_) => None,
+                Err(e) => {
+                    log::error!("Failed to read 2FA code from stdin: {}", e);
+                    None
+                }
+            }
+        })
+    }
+}
+
+/// Reads a one-time code from `VRC_2FA_CODE`, with `VRC_2FA_TYPE` set to `2fa` or `email`.
+///
+/// With a prefix, `{prefix}_VRC_2FA_CODE` and `{prefix}_VRC_2FA_TYPE` are read instead.
+#[derive(Debug, Clone, Default)]
+pub struct EnvTwoFactor {
+    prefix: Option<String>,
+}
+
+impl EnvTwoFactor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: Some(prefix.into()),
+        }
+    }
+}
+
+impl TwoFactorProvider for EnvTwoFactor {
+    fn provide_code<'a>(
+        &'a self,
+        methods: &'a [TwoFactorMethod],
+    ) -> BoxFuture<'a, Option<TwoFactorCode>> {
+        Box::pin(async move {
+            let prefix = self.prefix.as_deref();
+            let code = std::env::var(config::prefixed(prefix, "VRC_2FA_CODE"))
+                .ok()
+                .filter(|code| !code.is_empty())?;
+            let method = match std::env::var(config::prefixed(prefix, "VRC_2FA_TYPE")).as_deref() {
+                Ok("email") => TwoFactorMethod::EmailOtp,
+                _ => TwoFactorMethod::Totp,
+            };
+
+            if !methods.contains(&method) {
+                log::warn!(
+                    "VRC_2FA_TYPE is {:?} but VRChat asked for {:?}",
+                    method,
+                    methods
+                );
+            }
+
+            Some(TwoFactorCode::new(method, code))
+        })
+    }
+}
+
+/// Asks an async callback for each code.
+pub struct CallbackTwoFactor<F> {
+    callback: F,
+}
+
+impl<F, Fut> CallbackTwoFactor<F>
+where
+    F: Fn(Vec<TwoFactorMethod>) -> Fut + Send + Sync,
+    Fut: Future<Output = Option<TwoFactorCode>> + Send + 'static,
+{
+    pub fn new(callback: F) -> Self {
+        Self { callback }
+    }
+}
+
+impl<F, Fut> TwoFactorProvider for CallbackTwoFactor<F>
+where
+    F: Fn(Vec<TwoFactorMethod>) -> Fut + Send + Sync,
+    Fut: Future<Output = Option<TwoFactorCode>> + Send + 'static,
+{
+    fn provide_code<'a>(
+        &'a self,
+        methods: &'a [TwoFactorMethod],
+    ) -> BoxFuture<'a, Option<TwoFactorCode>> {
+        Box::pin((self.callback)(methods.to_vec()))
+    }
+}
+
+/// A pending request for a code, answered through `respond`.
+pub struct TwoFactorPrompt {
+    pub methods: Vec<TwoFactorMethod>,
+    pub respond: oneshot::Sender<Option<TwoFactorCode>>,
+}
+
+/// Sends each request as a `TwoFactorPrompt` to whoever holds the receiver, e.g. a GUI
+/// that shows a dialog and answers once the user typed the code.
+pub struct ChannelTwoFactor {
+    prompts: mpsc::Sender<TwoFactorPrompt>,
+}
+
+impl ChannelTwoFactor {
+    pub fn new() -> (Self, mpsc::Receiver<TwoFactorPrompt>) {
+        let (prompts, receiver) = mpsc::channel(1);
+        (Self { prompts }, receiver)
+    }
+}
+
+impl TwoFactorProvider for ChannelTwoFactor {
+    fn provide_code<'a>(
+        &'a self,
+        methods: &'a [TwoFactorMethod],
+    ) -> BoxFuture<'a, Option<TwoFactorCode>> {
+        Box::pin(async move {
+            let (respond, response) = oneshot::channel();
+            let prompt = TwoFactorPrompt {
+                methods: methods.to_vec(),
+                respond,
+            };
+            if self.prompts.send(prompt).await.is_err() {
+                log::warn!("Two-factor prompt receiver is gone");
+                return None;
+            }
+            // A dropped responder counts as a cancelled prompt.
+            response.await.ok().flatten()
+        })
+    }
+}
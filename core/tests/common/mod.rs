@@ -0,0 +1,104 @@
+//! Shared setup for the end-to-end tests.
+#![allow(dead_code)]
+
+use botan_core::account::AccountSession;
+use botan_core::client::VrcApiClient;
+use botan_core::database;
+use botan_core::models::LoginCredentials;
+use botan_core::session_store::{PersistedSession, PlainFileStore};
+use botan_core::two_factor::TwoFactorProvider;
+use botan_core::vrchatapi_models::{LimitedUser, User};
+use sea_orm::DatabaseConnection;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Installs a fresh, migrated SQLite database in a temporary file as the global connection.
+pub async fn scratch_database(name: &str) -> DatabaseConnection {
+    let path = scratch_path(&format!("{}.db", name));
+
+    database::init_database_with_url(&format!("sqlite://{}?mode=rwc", path.display()))
+        .await
+        .expect("scratch database");
+    database::get_db_connection().await.unwrap()
+}
+
+pub fn user(id: &str, display_name: &str) -> User {
+    User {
+        id: id.to_string(),
+        username: Some(display_name.to_lowercase()),
+        display_name: display_name.to_string(),
+        ..Default::default()
+    }
+}
+
+pub fn limited_user(id: &str, display_name: &str) -> LimitedUser {
+    LimitedUser {
+        id: id.to_string(),
+        username: Some(display_name.to_lowercase()),
+        display_name: display_name.to_string(),
+        ..Default::default()
+    }
+}
+
+pub fn friend_online(user_id: &str, location: &str) -> Value {
+    json!({
+        "userId": user_id,
+        "platform": "standalonewindows",
+        "location": location,
+        "canRequestInvite": true,
+        "user": user(user_id, user_id),
+    })
+}
+
+pub fn friend_offline(user_id: &str) -> Value {
+    json!({ "userId": user_id, "platform": "" })
+}
+
+/// Held by every test that uses the global database or account registry, which tests in
+/// the same binary would otherwise share concurrently.
+pub static SERIAL: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// A temporary file path unique to this test binary, removed if it already exists.
+pub fn scratch_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("botan-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Polls `check` until it returns `Some`, panicking with `what` after five seconds.
+pub async fn wait_for<T, F, Fut>(what: &str, mut check: F) -> T
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Option<T>>,
+{
+    let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(5);
+    loop {
+        if let Some(value) = check().await {
+            return value;
+        }
+        if tokio::time::Instant::now() > deadline {
+            panic!("timed out waiting for {}", what);
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+}
+
+/// An account against the API at `base_url`, logging in as `owner`/`hunter2`, whose
+/// cookies are kept in a scratch file named after `name`.
+pub fn account(
+    base_url: &str,
+    name: &str,
+    two_factor: Arc<dyn TwoFactorProvider>,
+) -> Arc<AccountSession> {
+    let store = PlainFileStore::new(scratch_path(&format!("{}-cookies.json", name)));
+    let session = PersistedSession::open(Box::new(store));
+    let client = VrcApiClient::with_base_url(base_url);
+    let account = AccountSession::new(client, Some(session), two_factor).unwrap();
+    account.set_credentials(&LoginCredentials {
+        username: "owner".to_string(),
+        password: Some("hunter2".to_string()),
+        auto_login_user_id: None,
+    });
+    Arc::new(account)
+}
@@ -0,0 +1,49 @@
+mod common;
+
+use botan_core::entities::{friend_sessions, prelude::*};
+use botan_core::services::event_service::{process_websocket_event, OfflineExpiry};
+use chrono::{Duration, Utc};
+use common::{friend_offline, friend_online, scratch_database, wait_for};
+use sea_orm::*;
+
+const OBSERVER: &str = "usr_observer";
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn backlogged_offline_is_committed_by_its_timer() {
+    let db = scratch_database("offline-backlog").await;
+
+    // Frames received long enough ago that the grace window is already over by the time
+    // they are processed, as when draining a backlog or the spill file.
+    let t0 = Utc::now() - Duration::hours(1);
+    let location = "wrld_x:12345~region(jp)";
+    for (received_at, event_type, content) in [
+        (t0, "friend-online", friend_online("usr_a", location)),
+        (
+            t0 + Duration::seconds(10),
+            "friend-offline",
+            friend_offline("usr_a"),
+        ),
+    ] {
+        process_websocket_event(
+            Some(OBSERVER),
+            event_type,
+            &content,
+            received_at,
+            OfflineExpiry::Timer,
+        )
+        .await
+        .unwrap();
+    }
+
+    let session = wait_for("the offline to be committed", || async {
+        FriendSessions::find()
+            .filter(friend_sessions::Column::UserId.eq("usr_a"))
+            .one(&db)
+            .await
+            .unwrap()
+            .filter(|session| session.ended_at.is_some())
+    })
+    .await;
+    assert_eq!(session.started_at, t0);
+    assert_eq!(session.ended_at, Some((t0 + Duration::seconds(10)).into()));
+}
@@ -0,0 +1,199 @@
+//! Scripted `MockPipelineServer` sessions run through a logged-in account, down to the rows
+//! in a scratch database.
+
+mod common;
+
+use botan_core::account::{AccountSession, GLOBAL_ACCOUNTS};
+use botan_core::auth;
+use botan_core::entities::{pipeline_events, prelude::*, user_location_history};
+use botan_core::testing::{MockAccount, MockApiServer, MockPipelineServer, ScriptStep};
+use botan_core::two_factor::DeferTwoFactor;
+use botan_core::PipelineAuthState;
+use common::{account, scratch_database, user, wait_for, SERIAL};
+use sea_orm::*;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+const OWNER: &str = "usr_owner";
+const LOCATION: &str = "wrld_x:12345~region(jp)";
+
+fn friend_location(user_id: &str, location: &str) -> Value {
+    json!({
+        "userId": user_id,
+        "location": location,
+        "travelingToLocation": "",
+        "worldId": location.split(':').next().unwrap_or_default(),
+        "canRequestInvite": true,
+        "user": user(user_id, user_id),
+    })
+}
+
+/// Logs in against `api` with the pipeline pointed at `pipeline`, reconnecting quickly.
+async fn login(
+    api: &MockApiServer,
+    pipeline: &MockPipelineServer,
+    name: &str,
+) -> Arc<AccountSession> {
+    std::env::set_var("PIPELINE_URL", pipeline.url());
+    std::env::set_var("PIPELINE_RECONNECT_INITIAL_MS", "50");
+    std::env::set_var("PIPELINE_RECONNECT_JITTER", "false");
+
+    let account = account(&api.base_url(), name, Arc::new(DeferTwoFactor));
+    let response = auth::login(&account).await;
+    assert!(response.success, "login failed: {}", response.message);
+    account
+}
+
+/// Waits for `count` archived frames, in the order they were received.
+async fn archived_events(db: &DatabaseConnection, count: usize) -> Vec<pipeline_events::Model> {
+    wait_for("archived pipeline events", || async {
+        let events = PipelineEvents::find()
+            .order_by_asc(pipeline_events::Column::Id)
+            .all(db)
+            .await
+            .unwrap();
+        (events.len() >= count).then_some(events)
+    })
+    .await
+}
+
+#[tokio::test]
+async fn double_encoded_location_is_persisted() {
+    let _serial = SERIAL.lock().await;
+    let db = scratch_database("pipeline-location").await;
+
+    let api = MockApiServer::start(MockAccount::new("owner", "hunter2", OWNER))
+        .await
+        .unwrap();
+    let pipeline = MockPipelineServer::start(vec![vec![ScriptStep::event(
+        "friend-location",
+        friend_location("usr_a", LOCATION),
+    )]])
+    .await
+    .unwrap();
+    login(&api, &pipeline, "pipeline-location").await;
+
+    let events = archived_events(&db, 1).await;
+    assert_eq!(events[0].event_type, "friend-location");
+    assert_eq!(events[0].outcome, "processed");
+    assert_eq!(events[0].observer_user_id.as_deref(), Some(OWNER));
+    // Archived decoded, not as the string it arrived in.
+    assert_eq!(
+        events[0]
+            .content
+            .as_ref()
+            .and_then(|c| c["location"].as_str()),
+        Some(LOCATION)
+    );
+
+    let history = UserLocationHistory::find()
+        .filter(user_location_history::Column::UserId.eq("usr_a"))
+        .all(&db)
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].location.as_deref(), Some(LOCATION));
+    assert_eq!(history[0].region.as_deref(), Some("jp"));
+    assert_eq!(history[0].observer_user_id.as_deref(), Some(OWNER));
+    assert_eq!(history[0].recorded_at, events[0].received_at);
+
+    GLOBAL_ACCOUNTS.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn malformed_frame_is_archived_as_invalid() {
+    let _serial = SERIAL.lock().await;
+    let db = scratch_database("pipeline-malformed").await;
+
+    let api = MockApiServer::start(MockAccount::new("owner", "hunter2", OWNER))
+        .await
+        .unwrap();
+    let pipeline = MockPipelineServer::start(vec![vec![
+        ScriptStep::raw("{not json"),
+        ScriptStep::event("friend-location", friend_location("usr_a", LOCATION)),
+    ]])
+    .await
+    .unwrap();
+    login(&api, &pipeline, "pipeline-malformed").await;
+
+    // The bad frame is archived straight away, the next one once it is processed.
+    let events = archived_events(&db, 2).await;
+    assert_eq!(events[0].event_type, "unknown");
+    assert_eq!(events[0].outcome, "invalid_frame");
+    assert_eq!(
+        events[0].content,
+        Some(Value::String("{not json".to_string()))
+    );
+    assert!(events[0].error.is_some());
+    assert_eq!(events[0].observer_user_id.as_deref(), Some(OWNER));
+    assert_eq!(events[1].outcome, "processed");
+
+    // A bad frame does not cost the connection.
+    assert_eq!(pipeline.connection_count(), 1);
+
+    GLOBAL_ACCOUNTS.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn dropped_connection_reconnects() {
+    let _serial = SERIAL.lock().await;
+    let db = scratch_database("pipeline-disconnect").await;
+
+    let api = MockApiServer::start(MockAccount::new("owner", "hunter2", OWNER))
+        .await
+        .unwrap();
+    let pipeline = MockPipelineServer::start(vec![
+        vec![ScriptStep::Disconnect],
+        vec![ScriptStep::event(
+            "friend-location",
+            friend_location("usr_a", LOCATION),
+        )],
+    ])
+    .await
+    .unwrap();
+    login(&api, &pipeline, "pipeline-disconnect").await;
+
+    let events = archived_events(&db, 1).await;
+    assert_eq!(events[0].outcome, "processed");
+    assert_eq!(pipeline.connection_count(), 2);
+    // Same session, so the token is reused.
+    let tokens = pipeline.auth_tokens();
+    assert_eq!(tokens[0], tokens[1]);
+    assert_eq!(api.login_count(), 1);
+
+    GLOBAL_ACCOUNTS.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn rejected_token_is_refreshed() {
+    let _serial = SERIAL.lock().await;
+    let _db = scratch_database("pipeline-auth-error").await;
+
+    let api = MockApiServer::start(MockAccount::new("owner", "hunter2", OWNER))
+        .await
+        .unwrap();
+    // The pause leaves time to expire the session before the token is rejected.
+    let pipeline = MockPipelineServer::start(vec![vec![
+        ScriptStep::Sleep(Duration::from_millis(300)),
+        ScriptStep::AuthError,
+    ]])
+    .await
+    .unwrap();
+    let account = login(&api, &pipeline, "pipeline-auth-error").await;
+    api.revoke_sessions();
+
+    let tokens = wait_for("reconnect with a new token", || async {
+        let tokens = pipeline.auth_tokens();
+        (tokens.len() >= 2).then_some(tokens)
+    })
+    .await;
+    assert_ne!(tokens[0], tokens[1]);
+    assert_eq!(api.login_count(), 2);
+    assert!(api.requests().iter().any(|r| r == "GET /api/1/auth"));
+
+    let status = account.pipeline_status().await.unwrap();
+    assert_eq!(status.auth_state, PipelineAuthState::Authenticated);
+
+    GLOBAL_ACCOUNTS.shutdown_all().await;
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use botan_core::entities::{friend_sessions, prelude::*};
+use botan_core::recording::{replay_file, RecordedFrame, ReplayTiming};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use common::{friend_offline, friend_online, scratch_database};
+use sea_orm::*;
+use serde_json::{json, Value};
+use std::io::Write;
+
+const OBSERVER: &str = "usr_observer";
+
+fn frame(received_at: DateTime<Utc>, event_type: &str, content: Value) -> RecordedFrame {
+    RecordedFrame {
+        received_at,
+        raw: json!({ "type": event_type, "content": content.to_string() }).to_string(),
+    }
+}
+
+#[tokio::test]
+async fn replay_expires_offlines_on_recorded_time() {
+    let db = scratch_database("replay").await;
+
+    // With the default 170s grace, the first offline is cancelled by coming back 50s later,
+    // and the second one has expired by the time the last frame arrives.
+    let t0 = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+    let location = "wrld_x:12345~region(jp)";
+    let frames = [
+        frame(t0, "friend-online", friend_online("usr_a", location)),
+        frame(
+            t0 + Duration::seconds(10),
+            "friend-offline",
+            friend_offline("usr_a"),
+        ),
+        frame(
+            t0 + Duration::seconds(60),
+            "friend-online",
+            friend_online("usr_a", location),
+        ),
+        frame(
+            t0 + Duration::seconds(100),
+            "friend-offline",
+            friend_offline("usr_a"),
+        ),
+        frame(
+            t0 + Duration::seconds(400),
+            "friend-online",
+            friend_online("usr_b", location),
+        ),
+    ];
+
+    let path = std::env::temp_dir().join(format!("botan-replay-{}.jsonl", std::process::id()));
+    let mut file = std::fs::File::create(&path).unwrap();
+    for frame in &frames {
+        writeln!(file, "{}", serde_json::to_string(frame).unwrap()).unwrap();
+    }
+    drop(file);
+
+    let summary = replay_file(&path, ReplayTiming::AsFastAsPossible, Some(OBSERVER))
+        .await
+        .unwrap();
+    assert_eq!(summary.frames, frames.len());
+    assert_eq!(summary.outcomes.get("processed"), Some(&frames.len()));
+
+    let sessions_a = FriendSessions::find()
+        .filter(friend_sessions::Column::UserId.eq("usr_a"))
+        .all(&db)
+        .await
+        .unwrap();
+    assert_eq!(sessions_a.len(), 1);
+    assert_eq!(sessions_a[0].started_at, t0);
+    assert_eq!(
+        sessions_a[0].ended_at,
+        Some((t0 + Duration::seconds(100)).into())
+    );
+    assert_eq!(sessions_a[0].observer_user_id.as_deref(), Some(OBSERVER));
+
+    let sessions_b = FriendSessions::find()
+        .filter(friend_sessions::Column::UserId.eq("usr_b"))
+        .all(&db)
+        .await
+        .unwrap();
+    assert_eq!(sessions_b.len(), 1);
+    assert_eq!(sessions_b[0].started_at, t0 + Duration::seconds(400));
+    assert_eq!(sessions_b[0].ended_at, None);
+
+    let _ = std::fs::remove_file(path);
+}
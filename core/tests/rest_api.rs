@@ -0,0 +1,228 @@
+//! Login and friend sync against `MockApiServer`, down to the rows in a scratch database.
+
+mod common;
+
+use botan_core::account::{AccountSession, GLOBAL_ACCOUNTS};
+use botan_core::auth;
+use botan_core::entities::{friendships, prelude::*, users};
+use botan_core::friends;
+use botan_core::testing::{MockAccount, MockApiServer, MockPipelineServer, MockTwoFactor};
+use botan_core::totp::{Totp, TotpTwoFactor};
+use botan_core::two_factor::{CallbackTwoFactor, DeferTwoFactor, TwoFactorCode, TwoFactorMethod};
+use botan_core::vrchatapi_apis::authentication_api;
+use botan_core::vrchatapi_models::{EitherUserOrTwoFactor, LimitedUser};
+use common::{account, limited_user, scratch_database, wait_for, SERIAL};
+use sea_orm::*;
+use std::sync::Arc;
+
+const OWNER: &str = "usr_owner";
+
+fn friends(prefix: &str, count: usize) -> Vec<LimitedUser> {
+    (0..count)
+        .map(|i| {
+            limited_user(
+                &format!("usr_{}_{}", prefix, i),
+                &format!("{} {}", prefix, i),
+            )
+        })
+        .collect()
+}
+
+/// Logs `account` in with `auth::login`, with its pipeline pointed at a silent mock.
+async fn login(account: &Arc<AccountSession>) -> EitherUserOrTwoFactor {
+    let pipeline = MockPipelineServer::start(Vec::new()).await.unwrap();
+    std::env::set_var("PIPELINE_URL", pipeline.url());
+
+    let response = auth::login(account).await;
+    assert!(response.success, "login failed: {}", response.message);
+    response.data.unwrap()
+}
+
+async fn active_friend_ids(db: &DatabaseConnection) -> Vec<String> {
+    let mut ids: Vec<String> = Friendships::find()
+        .filter(friendships::Column::OwnerUserId.eq(OWNER))
+        .filter(friendships::Column::IsActive.eq(true))
+        .all(db)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|f| f.friend_user_id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// Waits for the friend sync started by the login to reconcile `expected` friendships.
+async fn assert_friends_synced(db: &DatabaseConnection, expected: &[LimitedUser]) {
+    let mut expected_ids: Vec<String> = expected.iter().map(|f| f.id.clone()).collect();
+    expected_ids.sort();
+
+    let ids = wait_for("friendships", || async {
+        let ids = active_friend_ids(db).await;
+        (ids.len() == expected_ids.len()).then_some(ids)
+    })
+    .await;
+    assert_eq!(ids, expected_ids);
+
+    let friend_users = Users::find()
+        .filter(users::Column::IsFriend.eq(true))
+        .count(db)
+        .await
+        .unwrap();
+    assert_eq!(friend_users, expected.len() as u64);
+}
+
+#[tokio::test]
+async fn basic_auth_login_syncs_friends() {
+    let _serial = SERIAL.lock().await;
+    let db = scratch_database("basic-login").await;
+
+    let (online, offline) = (friends("online", 2), friends("offline", 1));
+    let api = MockApiServer::start(
+        MockAccount::new("owner", "hunter2", OWNER).with_friends(online.clone(), offline.clone()),
+    )
+    .await
+    .unwrap();
+    let account = account(&api.base_url(), "basic-login", Arc::new(DeferTwoFactor));
+
+    match login(&account).await {
+        EitherUserOrTwoFactor::CurrentUser(user) => assert_eq!(user.id, OWNER),
+        EitherUserOrTwoFactor::RequiresTwoFactorAuth(_) => panic!("login still requires 2FA"),
+    }
+    assert_eq!(api.login_count(), 1);
+    assert_eq!(account.user_id().as_deref(), Some(OWNER));
+    assert!(GLOBAL_ACCOUNTS.get(OWNER).await.is_some());
+
+    assert_friends_synced(&db, &[online, offline].concat()).await;
+
+    GLOBAL_ACCOUNTS.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn totp_two_factor_login() {
+    let _serial = SERIAL.lock().await;
+    let db = scratch_database("totp-login").await;
+
+    // A period longer than the test run keeps the code from rolling over mid-login.
+    let totp = Totp::new(b"12345678901234567890".to_vec(), 6, 1 << 40).unwrap();
+    let online = friends("online", 1);
+    let api = MockApiServer::start(
+        MockAccount::new("owner", "hunter2", OWNER)
+            .with_two_factor(MockTwoFactor::Totp(totp.current_code()))
+            .with_friends(online.clone(), Vec::new()),
+    )
+    .await
+    .unwrap();
+    let account = account(
+        &api.base_url(),
+        "totp-login",
+        Arc::new(TotpTwoFactor::new(totp)),
+    );
+
+    match login(&account).await {
+        EitherUserOrTwoFactor::CurrentUser(user) => assert_eq!(user.id, OWNER),
+        EitherUserOrTwoFactor::RequiresTwoFactorAuth(_) => panic!("login still requires 2FA"),
+    }
+    assert!(api
+        .requests()
+        .iter()
+        .any(|r| r == "POST /api/1/auth/twofactorauth/totp/verify"));
+
+    assert_friends_synced(&db, &online).await;
+
+    GLOBAL_ACCOUNTS.shutdown_all().await;
+}
+
+#[tokio::test]
+async fn email_otp_login() {
+    let _serial = SERIAL.lock().await;
+    let db = scratch_database("email-login").await;
+
+    let online = friends("online", 1);
+    let api = MockApiServer::start(
+        MockAccount::new("owner", "hunter2", OWNER)
+            .with_two_factor(MockTwoFactor::EmailOtp("424242".to_string()))
+            .with_friends(online.clone(), Vec::new()),
+    )
+    .await
+    .unwrap();
+    let two_factor = CallbackTwoFactor::new(|methods: Vec<TwoFactorMethod>| async move {
+        assert_eq!(methods, vec![TwoFactorMethod::EmailOtp]);
+        Some(TwoFactorCode::new(TwoFactorMethod::EmailOtp, "424242"))
+    });
+    let account = account(&api.base_url(), "email-login", Arc::new(two_factor));
+
+    match login(&account).await {
+        EitherUserOrTwoFactor::CurrentUser(user) => assert_eq!(user.id, OWNER),
+        EitherUserOrTwoFactor::RequiresTwoFactorAuth(_) => panic!("login still requires 2FA"),
+    }
+    assert!(api
+        .requests()
+        .iter()
+        .any(|r| r == "POST /api/1/auth/twofactorauth/emailotp/verify"));
+
+    assert_friends_synced(&db, &online).await;
+
+    GLOBAL_ACCOUNTS.shutdown_all().await;
+}
+
+/// Logs in through the API alone, without starting friend sync or a pipeline.
+async fn session_only(api: &MockApiServer, name: &str) -> Arc<AccountSession> {
+    let account = account(&api.base_url(), name, Arc::new(DeferTwoFactor));
+    let user = authentication_api::get_current_user(&account.config())
+        .await
+        .unwrap();
+    assert!(matches!(user, EitherUserOrTwoFactor::CurrentUser(_)));
+    account
+}
+
+fn friends_requests(api: &MockApiServer) -> Vec<String> {
+    api.requests()
+        .into_iter()
+        .filter(|r| r.starts_with("GET /api/1/auth/user/friends"))
+        .collect()
+}
+
+#[tokio::test]
+async fn fetch_all_friends_retries_rate_limits() {
+    let online = friends("online", 3);
+    let api = MockApiServer::start(
+        MockAccount::new("owner", "hunter2", OWNER).with_friends(online.clone(), Vec::new()),
+    )
+    .await
+    .unwrap();
+    let account = session_only(&api, "rate-limit").await;
+
+    api.rate_limit_next(2);
+    let fetched = friends::fetch_all_friends(&account.config()).await.unwrap();
+
+    assert_eq!(fetched.len(), online.len());
+    // Two rejected attempts at the first page, then the online and offline pages.
+    assert_eq!(friends_requests(&api).len(), 4);
+}
+
+#[tokio::test]
+async fn friend_sync_pages_past_one_hundred() {
+    let _serial = SERIAL.lock().await;
+    let db = scratch_database("friend-paging").await;
+
+    let (online, offline) = (friends("online", 150), friends("offline", 30));
+    let api = MockApiServer::start(
+        MockAccount::new("owner", "hunter2", OWNER).with_friends(online.clone(), offline.clone()),
+    )
+    .await
+    .unwrap();
+    let account = session_only(&api, "friend-paging").await;
+
+    friends::sync_friend_list(&account.config(), OWNER)
+        .await
+        .unwrap();
+
+    let requests = friends_requests(&api);
+    assert_eq!(requests.len(), 3, "{:?}", requests);
+    assert!(requests[1].contains("offset=100"), "{:?}", requests);
+
+    let all = [online, offline].concat();
+    assert_eq!(active_friend_ids(&db).await.len(), all.len());
+    assert_friends_synced(&db, &all).await;
+}
@@ -2,36 +2,37 @@ use botan_core::auth::auth_login_and_get_current_user;
 use botan_core::models::response::ApiResponse;
 use botan_core::models::TwoFactorVerifyResult;
 use botan_core::models::{EitherTwoFactorAuthCodeType, LoginCredentials};
+use botan_core::session_store;
+use botan_core::two_factor::DeferTwoFactor;
 use botan_core::vrchatapi_models::EitherUserOrTwoFactor;
-// use tauri::Manager;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::Manager;
 
-// fn get_cookies_path(app_handle: &tauri::AppHandle) -> Option<String> {
-//     match app_handle.path().app_config_dir() {
-//         Ok(cookies_path_buf) => match cookies_path_buf.to_str() {
-//             Some(path_str) => Some(path_str.to_string()),
-//             None => {
-//                 log::error!("Failed to convert cookies path to string");
-//                 None
-//             }
-//         },
-//         Err(e) => {
-//             log::error!("Failed to get app config directory: {}", e);
-//             None
-//         }
-//     }
-// }
+fn get_cookies_path(app_handle: &tauri::AppHandle) -> Option<PathBuf> {
+    match app_handle.path().app_config_dir() {
+        Ok(config_dir) => Some(config_dir.join("cookies.json")),
+        Err(e) => {
+            log::error!("Failed to get app config directory: {}", e);
+            None
+        }
+    }
+}
 
 #[tauri::command]
 pub async fn login(
-    // app_handle: tauri::AppHandle,
+    app_handle: tauri::AppHandle,
     credentials: Option<LoginCredentials>,
 ) -> ApiResponse<EitherUserOrTwoFactor> {
     log::info!(
         "Tauri command, api - 'auth/user', login, credentials: {:?}",
         credentials
     );
-    // let cookies_path = get_cookies_path(&app_handle);
-    auth_login_and_get_current_user(&credentials, &Some(true)).await
+    if let Some(cookies_path) = get_cookies_path(&app_handle) {
+        session_store::set_session_path(cookies_path);
+    }
+    // The frontend shows its own 2FA dialog and calls `verify2_fa` itself.
+    auth_login_and_get_current_user(&credentials, &Some(true), Arc::new(DeferTwoFactor)).await
 }
 
 #[tauri::command]
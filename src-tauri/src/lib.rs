@@ -1,4 +1,5 @@
-use tauri::Manager;
+use botan_core::event_bus::GLOBAL_EVENT_BUS;
+use tauri::{Emitter, Manager};
 
 pub mod commands;
 
@@ -13,6 +14,17 @@ pub fn run() {
                 let window = app.get_webview_window("main").unwrap();
                 window.open_devtools();
             }
+
+            let app_handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut subscription = GLOBAL_EVENT_BUS.subscribe("tauri");
+                while let Some(event) = subscription.recv().await {
+                    if let Err(e) = app_handle.emit("domain-event", &event) {
+                        log::error!("Failed to emit domain event: {}", e);
+                    }
+                }
+            });
+
             Ok(())
         })
         .plugin(tauri_plugin_store::Builder::new().build())
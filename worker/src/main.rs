@@ -1,7 +1,43 @@
-use botan_core::models::{EitherTwoFactorAuthCodeType, LoginCredentials};
-use botan_core::vrchatapi_models::{EitherUserOrTwoFactor, TwoFactorAuthCode, TwoFactorEmailCode};
-use botan_core::{auth, database};
+use botan_core::account::{AccountSession, GLOBAL_ACCOUNTS};
+use botan_core::models::LoginCredentials;
+use botan_core::recording::{self, ReplayTiming};
+use botan_core::secrets;
+use botan_core::session_store::PersistedSession;
+use botan_core::totp::TotpTwoFactor;
+use botan_core::two_factor::{EnvTwoFactor, TwoFactorProvider};
+use botan_core::vrchatapi_models::EitherUserOrTwoFactor;
+use botan_core::{auth, config, database};
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Feed a pipeline recording (see PIPELINE_RECORD_PATH) into a scratch database
+    Replay {
+        /// JSONL file written by the recorder
+        file: PathBuf,
+        /// Wait between frames as long as they were apart when recorded
+        #[arg(long)]
+        original_timing: bool,
+        /// Database to replay into; defaults to a new SQLite file next to the recording
+        #[arg(long)]
+        database_url: Option<String>,
+        /// User id of the account the recording was made with, to tag replayed rows
+        #[arg(long)]
+        observer: Option<String>,
+    },
+    /// Encrypt a secret read from stdin with BOTAN_ENCRYPTION_KEY, e.g. for VRC_TOTP_SECRET_FILE
+    EncryptSecret,
+}
 
 #[tokio::main]
 async fn main() {
@@ -12,22 +48,38 @@ async fn main() {
         .install_default()
         .expect("Failed to install rustls crypto provider");
 
+    match Cli::parse().command {
+        Some(Command::Replay {
+            file,
+            original_timing,
+            database_url,
+            observer,
+        }) => {
+            replay(file, original_timing, database_url, observer).await;
+            return;
+        }
+        Some(Command::EncryptSecret) => {
+            encrypt_secret();
+            return;
+        }
+        None => {}
+    }
+
     if let Err(e) = database::init_database().await {
         log::error!("Failed to initialize database: {}", e);
         std::process::exit(1);
     }
     println!("Database initialized successfully");
 
-    let username = std::env::var("USERNAME").expect("NO USERNAME");
-    let password = std::env::var("PASSWORD").expect("NO PASSWORD");
-    let credentials = Some(LoginCredentials {
-        username,
-        password: Some(password),
-        auto_login_user_id: None,
-    });
-
-    let auth_result = authenticate(&credentials).await;
-    if !auth_result {
+    let mut logged_in = 0;
+    for account in account_configs() {
+        if authenticate(&account).await {
+            logged_in += 1;
+        } else {
+            log::error!("Authentication failed for {}", account.credentials.username);
+        }
+    }
+    if logged_in == 0 {
         log::error!("Authentication failed");
         std::process::exit(1);
     }
@@ -35,17 +87,166 @@ async fn main() {
     // waiting
     wait_for_shutdown().await;
 
+    GLOBAL_ACCOUNTS.shutdown_all().await;
+
     println!("Application shutdown complete");
 }
 
-async fn authenticate(credentials: &Option<LoginCredentials>) -> bool {
-    match auth::auth_login_and_get_current_user(credentials, &Some(true)).await {
+async fn replay(
+    file: PathBuf,
+    original_timing: bool,
+    database_url: Option<String>,
+    observer: Option<String>,
+) {
+    let database_url = database_url.unwrap_or_else(|| {
+        let started = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        format!("sqlite://{}.replay-{}.db?mode=rwc", file.display(), started)
+    });
+
+    if let Err(e) = database::init_database_with_url(&database_url).await {
+        log::error!("Failed to initialize replay database: {}", e);
+        std::process::exit(1);
+    }
+
+    let timing = if original_timing {
+        ReplayTiming::Original
+    } else {
+        ReplayTiming::AsFastAsPossible
+    };
+
+    match recording::replay_file(&file, timing, observer.as_deref()).await {
+        Ok(summary) => {
+            println!(
+                "Replayed {} frame(s) into {} ({} unreadable line(s))",
+                summary.frames, database_url, summary.unreadable_lines
+            );
+            for (outcome, count) in summary.outcomes {
+                println!("  {}: {}", outcome, count);
+            }
+        }
+        Err(e) => {
+            log::error!("Replay failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn encrypt_secret() {
+    let key = match secrets::encryption_key() {
+        Ok(key) => key,
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut secret = String::new();
+    if let Err(e) = std::io::stdin().read_line(&mut secret) {
+        log::error!("Failed to read secret from stdin: {}", e);
+        std::process::exit(1);
+    }
+
+    println!("{}", secrets::encrypt(&key, secret.trim()));
+}
+
+/// One VRChat account to watch.
+struct AccountConfig {
+    /// Prefix of this account's variables, `None` for the unprefixed ones.
+    prefix: Option<String>,
+    credentials: LoginCredentials,
+}
+
+/// The accounts to log in: one per prefix in `BOTAN_ACCOUNTS` (e.g. `MAIN,ALT`, read from
+/// `MAIN_USERNAME`, `MAIN_PASSWORD`, `MAIN_VRC_TOTP_SECRET`, ...), or the single account
+/// from `USERNAME` and `PASSWORD` when it is unset.
+fn account_configs() -> Vec<AccountConfig> {
+    let prefixes: Vec<Option<String>> = match std::env::var("BOTAN_ACCOUNTS") {
+        Ok(accounts) if !accounts.trim().is_empty() => accounts
+            .split(',')
+            .map(str::trim)
+            .filter(|prefix| !prefix.is_empty())
+            .map(|prefix| Some(prefix.to_string()))
+            .collect(),
+        _ => vec![None],
+    };
+
+    prefixes
+        .into_iter()
+        .map(|prefix| {
+            let var = |name: &str| {
+                let name = config::prefixed(prefix.as_deref(), name);
+                match std::env::var(&name) {
+                    Ok(value) if !value.is_empty() => value,
+                    _ => {
+                        log::error!("{} is not set", name);
+                        std::process::exit(1);
+                    }
+                }
+            };
+            let credentials = LoginCredentials {
+                username: var("USERNAME"),
+                password: Some(var("PASSWORD")),
+                auto_login_user_id: None,
+            };
+            AccountConfig {
+                prefix,
+                credentials,
+            }
+        })
+        .collect()
+}
+
+/// Generates codes from the account's TOTP secret when one is configured, otherwise falls
+/// back to its one-shot `VRC_2FA_CODE`.
+fn two_factor_provider(prefix: Option<&str>) -> Arc<dyn TwoFactorProvider> {
+    match TotpTwoFactor::from_env_with_prefix(prefix) {
+        Ok(Some(totp)) => {
+            log::info!("Using TOTP secret for 2FA");
+            Arc::new(totp)
+        }
+        Ok(None) => Arc::new(match prefix {
+            Some(prefix) => EnvTwoFactor::with_prefix(prefix),
+            None => EnvTwoFactor::new(),
+        }),
+        Err(e) => {
+            log::error!("Invalid TOTP secret: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Logs in one account with a session and cookie file of its own.
+async fn authenticate(account_config: &AccountConfig) -> bool {
+    let prefix = account_config.prefix.as_deref();
+    let credentials = &account_config.credentials;
+    let session = PersistedSession::for_account(&credentials.username);
+    let two_factor = two_factor_provider(prefix);
+    let account = match AccountSession::with_defaults(session, two_factor) {
+        Ok(account) => Arc::new(account),
+        Err(e) => {
+            log::error!("Failed to build HTTP client: {}", e);
+            return false;
+        }
+    };
+    account.set_credentials(credentials);
+
+    match auth::login(&account).await {
         api_response if api_response.success => match api_response.data {
             Some(EitherUserOrTwoFactor::CurrentUser(user)) => {
                 log::info!("Login successful: {}", user.display_name);
                 true
             }
-            Some(EitherUserOrTwoFactor::RequiresTwoFactorAuth(_)) => handle_2fa(credentials).await,
+            Some(EitherUserOrTwoFactor::RequiresTwoFactorAuth(_)) => {
+                log::error!(
+                    "2FA required, set {} or {}",
+                    config::prefixed(prefix, "VRC_TOTP_SECRET"),
+                    config::prefixed(prefix, "VRC_2FA_CODE")
+                );
+                false
+            }
             None => {
                 log::error!("No user data returned");
                 false
@@ -58,39 +259,6 @@ async fn authenticate(credentials: &Option<LoginCredentials>) -> bool {
     }
 }
 
-async fn handle_2fa(credentials: &Option<LoginCredentials>) -> bool {
-    if let Ok(two_fa_code_str) = std::env::var("VRC_2FA_CODE") {
-        let two_fa_type_str = std::env::var("VRC_2FA_TYPE").unwrap_or("2fa".to_string());
-        let code_to_verify = if two_fa_type_str == "email" {
-            EitherTwoFactorAuthCodeType::IsB(TwoFactorEmailCode {
-                code: two_fa_code_str,
-            })
-        } else {
-            EitherTwoFactorAuthCodeType::IsA(TwoFactorAuthCode {
-                code: two_fa_code_str,
-            })
-        };
-
-        match auth::auth_verify2_fa(&two_fa_type_str, code_to_verify).await {
-            verify_response if verify_response.success => {
-                match auth::auth_login_and_get_current_user(credentials, &Some(false)).await {
-                    login_response if login_response.success => {
-                        if let Some(EitherUserOrTwoFactor::CurrentUser(user)) = login_response.data
-                        {
-                            log::info!("Login successful after 2FA: {}", user.display_name);
-                            return true;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-            _ => {}
-        }
-    }
-    log::error!("2FA authentication failed");
-    false
-}
-
 async fn wait_for_shutdown() {
     println!("Service is running. Waiting for shutdown signal...");
 